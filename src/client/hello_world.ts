@@ -59,14 +59,52 @@ const PROGRAM_SO_PATH = path.join(PROGRAM_PATH, 'helloworld.so');
  */
 const PROGRAM_KEYPAIR_PATH = path.join(PROGRAM_PATH, 'helloworld-keypair.json');
 
+/**
+ * Borsh type of the program's counter, which has to match the features it was built with:
+ * 'u32' by default and 'i32' with signed-counter.
+ * The payload layout in createSetInstruction has to be changed alongside it
+ */
+const COUNTER_TYPE = 'u32';
+
 /**
  * The state of a greeting account managed by the hello world program
  */
 class GreetingAccount {
   counter = 0;
-  constructor(fields: {counter: number} | undefined = undefined) {
+  monotonic = 0;
+  last_writer = new Uint8Array(32);
+  op_count = 0;
+  commitment = new Uint8Array(32);
+  checksum = 0;
+  return_verbosity = 0;
+  allow_zero_result = 1;
+  product_started = 0;
+  _reserved = new Uint8Array(29);
+  constructor(
+    fields:
+      | {
+          counter: number;
+          monotonic: number;
+          last_writer: Uint8Array;
+          op_count: number;
+          commitment: Uint8Array;
+          return_verbosity: number;
+          allow_zero_result: number;
+          product_started: number;
+          checksum: number;
+        }
+      | undefined = undefined,
+  ) {
     if (fields) {
       this.counter = fields.counter;
+      this.monotonic = fields.monotonic;
+      this.last_writer = fields.last_writer;
+      this.op_count = fields.op_count;
+      this.commitment = fields.commitment;
+      this.return_verbosity = fields.return_verbosity;
+      this.allow_zero_result = fields.allow_zero_result;
+      this.product_started = fields.product_started;
+      this.checksum = fields.checksum;
     }
   }
 }
@@ -75,7 +113,29 @@ class GreetingAccount {
  * Borsh schema definition for greeting accounts
  */
 const GreetingSchema = new Map([
-  [GreetingAccount, {kind: 'struct', fields: [['counter', 'u32']]}],
+  [
+    GreetingAccount,
+    {
+      kind: 'struct',
+      fields: [
+        ['counter', COUNTER_TYPE],
+        ['monotonic', 'u8'],
+        ['last_writer', [32]],
+        ['op_count', 'u64'],
+        // the full 32 byte hash. Accounts made when only 20 bytes were kept, or before the
+        // reserved space at the end came back, are too short for this layout and have to
+        // be created again
+        ['commitment', [32]],
+        ['checksum', 'u32'],
+        // taken from the reserved space, so the size stays the same
+        ['return_verbosity', 'u8'],
+        ['allow_zero_result', 'u8'],
+        ['product_started', 'u8'],
+        // always zero, new fields take their space from here so the size stays the same
+        ['_reserved', [29]],
+      ],
+    },
+  ],
 ]);
 
 /**
@@ -83,7 +143,17 @@ const GreetingSchema = new Map([
  */
 const GREETING_SIZE = borsh.serialize(
   GreetingSchema,
-  new GreetingAccount(),
+  new GreetingAccount({
+    counter: 0,
+    monotonic: 0,
+    last_writer: new Uint8Array(32),
+    op_count: 0,
+    commitment: new Uint8Array(32),
+    return_verbosity: 0,
+    allow_zero_result: 1,
+    product_started: 0,
+    checksum: 0,
+  }),
 ).length;
 
 /**
@@ -230,6 +300,7 @@ function createSetInstruction(setAmount: number): Buffer{
     throw 'Error: set instruction parameter out of range';
   }
   //we define the layout as a struct with a single value called instruction
+  //the value is a COUNTER_TYPE, keep the range check and this field in step with it
   const layout = BufferLayout.struct([
     BufferLayout.u8('instruction'),
     BufferLayout.u32('value')
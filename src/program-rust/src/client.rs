@@ -0,0 +1,327 @@
+//! Builders for off-chain clients, one per instruction, that mark every account with the
+//! writability and signer flags the program expects so callers can't get them wrong
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use crate::error::GreetingError;
+#[cfg(feature = "arithmetic-ext")]
+use crate::instruction::MAX_BATCH;
+use crate::instruction::HelloInstruction;
+use crate::{Counter, GreetingAccount};
+
+impl GreetingError {
+    /// Decode the code carried by `ProgramError::Custom` back into the error
+    pub fn from_u32(code: u32) -> Option<Self> {
+        match code {
+            100 => Some(GreetingError::Overflow),
+            101 => Some(GreetingError::NonMonotonic),
+            102 => Some(GreetingError::Underflow),
+            103 => Some(GreetingError::CasMismatch),
+            104 => Some(GreetingError::ChecksumMismatch),
+            105 => Some(GreetingError::AssertionFailed),
+            106 => Some(GreetingError::DuplicateAccount),
+            107 => Some(GreetingError::DataLenMismatch),
+            108 => Some(GreetingError::WriteVerificationFailed),
+            109 => Some(GreetingError::CommitmentMismatch),
+            110 => Some(GreetingError::TooManyAccounts),
+            111 => Some(GreetingError::OutsideWindow),
+            112 => Some(GreetingError::StoredChecksumMismatch),
+            113 => Some(GreetingError::ZeroResultForbidden),
+            _ => None,
+        }
+    }
+}
+
+impl HelloInstruction {
+    /// Check that `input` is instruction data the program would accept, without paying for
+    /// a transaction to find out
+    pub fn validate(input: &[u8]) -> Result<(), ProgramError> {
+        HelloInstruction::unpack(input).map(|_| ())
+    }
+}
+
+/// Number of bytes to allocate when creating a greeting account with `create_account`
+pub fn account_size() -> usize {
+    GreetingAccount::LEN
+}
+
+/// Add the default step to the greeted account's counter
+pub fn increment(program_id: &Pubkey, greeted: &Pubkey) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::Increment)
+}
+
+/// Add the default step to the greeted account's counter and log `memo` with the change
+#[cfg(feature = "arithmetic-ext")]
+pub fn increment_with_memo(program_id: &Pubkey, greeted: &Pubkey, memo: &str) -> Instruction {
+    let instruction = HelloInstruction::IncrementWithMemo(memo.to_string());
+    counter_instruction(program_id, greeted, instruction)
+}
+
+/// Subtract the default step from the greeted account's counter
+pub fn decrement(program_id: &Pubkey, greeted: &Pubkey) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::Decrement)
+}
+
+/// Overwrite the greeted account's counter with `value`
+pub fn set(program_id: &Pubkey, greeted: &Pubkey, value: Counter) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::Set(value))
+}
+
+/// Overwrite the greeted account's counter with `value`, failing if its data isn't
+/// `expected_len` bytes, which usually means the wrong account was passed
+#[cfg(feature = "arithmetic-ext")]
+pub fn set_checked(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    expected_len: u32,
+    value: Counter,
+) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::SetChecked { expected_len, value })
+}
+
+/// Commit the greeted account to a future value, `hash` is the `commitment_hash` of it
+#[cfg(feature = "governance")]
+pub fn commit(program_id: &Pubkey, greeted: &Pubkey, hash: [u8; 32]) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::Commit(hash))
+}
+
+/// Set the greeted account's counter to the committed `value`, proven by its `salt`
+#[cfg(feature = "governance")]
+pub fn reveal(program_id: &Pubkey, greeted: &Pubkey, value: Counter, salt: [u8; 32]) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::Reveal { value, salt })
+}
+
+/// Overwrite the greeted account's counter with `value` if the cluster's unix time is
+/// between `start` and `end`, both inclusive
+#[cfg(feature = "governance")]
+pub fn set_in_window(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    value: Counter,
+    start: i64,
+    end: i64,
+) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::SetInWindow { value, start, end })
+}
+
+/// Change the greeted account's counter by `percent` percent of its current value
+#[cfg(feature = "arithmetic-ext")]
+pub fn adjust_percent(program_id: &Pubkey, greeted: &Pubkey, percent: i32) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::AdjustPercent(percent))
+}
+
+/// Add the default step to the greeted account's counter only if it currently equals `expected`
+#[cfg(feature = "arithmetic-ext")]
+pub fn increment_if_equal(program_id: &Pubkey, greeted: &Pubkey, expected: Counter) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::IncrementIfEqual(expected))
+}
+
+/// Add the sum of `deltas` to the greeted account's counter in a single checked step.
+/// More than `MAX_BATCH` deltas is refused here rather than by the program
+#[cfg(feature = "arithmetic-ext")]
+pub fn add_many(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    deltas: Vec<Counter>,
+) -> Result<Instruction, ProgramError> {
+    if deltas.len() > MAX_BATCH {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(counter_instruction(program_id, greeted, HelloInstruction::AddMany(deltas)))
+}
+
+/// Multiply the greeted account's counter by `sample`
+#[cfg(feature = "arithmetic-ext")]
+pub fn mul_sample(program_id: &Pubkey, greeted: &Pubkey, sample: u32) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::MulSample(sample))
+}
+
+/// Pick what mutations of the greeted account write to return data, a `VERBOSITY_*` level
+#[cfg(feature = "governance")]
+pub fn set_verbosity(program_id: &Pubkey, greeted: &Pubkey, level: u8) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::SetVerbosity(level))
+}
+
+/// Allow or forbid MulSample and AdjustPercent zeroing the greeted account's counter
+#[cfg(feature = "governance")]
+pub fn set_allow_zero_result(program_id: &Pubkey, greeted: &Pubkey, allow: bool) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::SetAllowZeroResult(allow))
+}
+
+/// Add `delta` to the greeted account's counter, failing unless the result is `expected`
+#[cfg(feature = "arithmetic-ext")]
+pub fn add_then_assert(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    delta: Counter,
+    expected: Counter,
+) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::AddThenAssert { delta, expected })
+}
+
+/// Permanently forbid lowering the greeted account's counter
+#[cfg(feature = "governance")]
+pub fn set_monotonic(program_id: &Pubkey, greeted: &Pubkey) -> Instruction {
+    counter_instruction(program_id, greeted, HelloInstruction::SetMonotonic)
+}
+
+/// Log the greeted account's state as hex, only the account's data is read
+#[cfg(feature = "debug-export")]
+pub fn export_hex(program_id: &Pubkey, greeted: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::ExportHex.pack(),
+        vec![AccountMeta::new_readonly(*greeted, false)],
+    )
+}
+
+/// Fail unless the greeted account's fields still add up to `checksum`, the value stored in
+/// its `checksum` field when it was last written
+pub fn verify_checksum(program_id: &Pubkey, greeted: &Pubkey, checksum: u32) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::VerifyChecksum(checksum).pack(),
+        vec![AccountMeta::new_readonly(*greeted, false)],
+    )
+}
+
+/// Fail unless the greeted account's counter equals `expected`, only the account is read
+pub fn assert_equals(program_id: &Pubkey, greeted: &Pubkey, expected: Counter) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::AssertEquals(expected).pack(),
+        vec![AccountMeta::new_readonly(*greeted, false)],
+    )
+}
+
+/// Have the program return the greeted account's data length, only the account is read
+pub fn get_data_len(program_id: &Pubkey, greeted: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::GetDataLen.pack(),
+        vec![AccountMeta::new_readonly(*greeted, false)],
+    )
+}
+
+/// Have the program return its own id, only the greeted account is read
+pub fn who_am_i(program_id: &Pubkey, greeted: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::WhoAmI.pack(),
+        vec![AccountMeta::new_readonly(*greeted, false)],
+    )
+}
+
+/// Have the program return the counters of all of `greeted`, in order, each account is
+/// only read
+#[cfg(feature = "multi-account")]
+pub fn get_many(program_id: &Pubkey, greeted: &[Pubkey]) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::GetMany.pack(),
+        greeted
+            .iter()
+            .map(|greeted| AccountMeta::new_readonly(*greeted, false))
+            .collect(),
+    )
+}
+
+/// Copy the state of `source` into `destination`, only the destination is written
+#[cfg(feature = "multi-account")]
+pub fn snapshot(program_id: &Pubkey, source: &Pubkey, destination: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::Snapshot.pack(),
+        vec![
+            AccountMeta::new_readonly(*source, false),
+            AccountMeta::new(*destination, false),
+        ],
+    )
+}
+
+/// Decrement the greeted account, closing it into `destination` once it reaches zero. The
+/// greeted account signs, since closing it gives its lamports away
+#[cfg(feature = "multi-account")]
+pub fn decrement_and_maybe_close(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::DecrementAndMaybeClose.pack(),
+        vec![AccountMeta::new(*greeted, true), AccountMeta::new(*destination, false)],
+    )
+}
+
+/// Set the greeted account's counter from the value at `offset` in `oracle`, an account
+/// owned by `owner` that is only read
+#[cfg(feature = "multi-account")]
+pub fn set_from_oracle(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    oracle: &Pubkey,
+    offset: u32,
+    owner: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::SetFromOracle { offset, owner: *owner }.pack(),
+        vec![AccountMeta::new(*greeted, false), AccountMeta::new_readonly(*oracle, false)],
+    )
+}
+
+/// Set the greeted account's counter to the checksum of `source`'s data, which is only read
+#[cfg(feature = "multi-account")]
+pub fn set_to_checksum(program_id: &Pubkey, greeted: &Pubkey, source: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::SetToChecksum.pack(),
+        vec![AccountMeta::new(*greeted, false), AccountMeta::new_readonly(*source, false)],
+    )
+}
+
+/// Store the average of the `a` and `b` counters in `destination`
+#[cfg(feature = "multi-account")]
+pub fn merge_average(
+    program_id: &Pubkey,
+    destination: &Pubkey,
+    a: &Pubkey,
+    b: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &HelloInstruction::MergeAverage.pack(),
+        vec![
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(*a, false),
+            AccountMeta::new_readonly(*b, false),
+        ],
+    )
+}
+
+/// Have `signer` sign `instruction`, so the program credits it as the account's
+/// `last_writer`. A signer the instruction already lists is marked as signing, any other is
+/// appended as a read only signer that the program ignores beyond that
+pub fn signed_by(mut instruction: Instruction, signer: &Pubkey) -> Instruction {
+    match instruction.accounts.iter_mut().find(|meta| meta.pubkey == *signer) {
+        Some(meta) => meta.is_signer = true,
+        None => instruction.accounts.push(AccountMeta::new_readonly(*signer, true)),
+    }
+    instruction
+}
+
+//instructions that only touch the greeted account, which is written but needn't sign
+fn counter_instruction(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    instruction: HelloInstruction,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &instruction.pack(),
+        vec![AccountMeta::new(*greeted, false)],
+    )
+}
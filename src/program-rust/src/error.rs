@@ -0,0 +1,44 @@
+use solana_program::program_error::ProgramError;
+
+/// Errors specific to the greeting program, surfaced to clients as `ProgramError::Custom`.
+/// Every variant has an explicit code so reordering them never changes what clients see
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GreetingError {
+    /// The operation would push the counter past `Counter::MAX`
+    Overflow = 100,
+    /// The account is monotonic and the operation would lower the counter
+    NonMonotonic = 101,
+    /// The operation would push the counter below its minimum value
+    Underflow = 102,
+    /// A compare-and-swap style instruction found a different counter than expected
+    CasMismatch = 103,
+    /// The state no longer matches the checksum the client provided
+    ChecksumMismatch = 104,
+    /// The counter didn't hold the value an AssertEquals or AddThenAssert expected
+    AssertionFailed = 105,
+    /// The same account was passed in two positions that must hold different accounts
+    DuplicateAccount = 106,
+    /// The greeted account's data length isn't the one the client expected
+    DataLenMismatch = 107,
+    /// Reading back a freshly written state gave something else, only checked with the
+    /// `verify-writes` feature
+    WriteVerificationFailed = 108,
+    /// A Reveal didn't match the stored commitment, or there was nothing committed
+    CommitmentMismatch = 109,
+    /// More accounts were passed than the instruction can handle
+    TooManyAccounts = 110,
+    /// A SetInWindow ran before its window opened or after it closed
+    OutsideWindow = 111,
+    /// An account's fields don't match the checksum stored with them, only checked with the
+    /// `verify-reads` feature
+    StoredChecksumMismatch = 112,
+    /// A MulSample or AdjustPercent would take a non zero counter to zero on an account
+    /// that doesn't allow it
+    ZeroResultForbidden = 113,
+}
+
+impl From<GreetingError> for ProgramError {
+    fn from(e: GreetingError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
@@ -1,40 +1,773 @@
-use solana_program::{program_error::ProgramError};
-use std::convert::TryInto;
+use solana_program::{program::MAX_RETURN_DATA, program_error::ProgramError};
+#[cfg(feature = "multi-account")]
+use solana_program::pubkey::Pubkey;
+use std::convert::{TryFrom, TryInto};
+use std::mem;
+use crate::Counter;
+#[cfg(feature = "governance")]
+use crate::VERBOSITY_FULL;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum HelloInstruction{
+    /// Add the default step to the counter. It packs to the lone tag byte `[0]`, the
+    /// smallest instruction there is, for clients that increment often (tag 0)
     Increment,
     Decrement,
-    Set(u32)
+    Set(Counter),
+    /// Copy the full serialized state into a second, program owned account (tag 49)
+    #[cfg(feature = "multi-account")]
+    Snapshot,
+    /// Store the average of two other program owned accounts' counters (tag 52)
+    #[cfg(feature = "multi-account")]
+    MergeAverage,
+    /// Permanently forbid lowering the counter from now on (tag 57)
+    #[cfg(feature = "governance")]
+    SetMonotonic,
+    /// Log the serialized account as a hex string, for manual inspection (tag 58)
+    #[cfg(feature = "debug-export")]
+    ExportHex,
+    /// Change the counter by a signed percentage of its current value (tag 59)
+    #[cfg(feature = "arithmetic-ext")]
+    AdjustPercent(i32),
+    /// Increment by the default step, only when the counter equals the given value (tag 60)
+    #[cfg(feature = "arithmetic-ext")]
+    IncrementIfEqual(Counter),
+    /// Fail unless the state's `fields_checksum`, what the program stores in `checksum` on
+    /// every write, equals the given value, without mutating (tag 61)
+    VerifyChecksum(u32),
+    /// Return the greeted account's data length as a little endian u64, without mutating. The
+    /// state isn't deserialized, so this works whatever the account's size (tag 64)
+    GetDataLen,
+    /// Add the sum of every delta in one step, all or nothing. The payload is a shortvec
+    /// (compact u16) count followed by that many counters, at most `MAX_BATCH` (tag 66)
+    #[cfg(feature = "arithmetic-ext")]
+    AddMany(Vec<Counter>),
+    /// Fail unless the counter equals the given value, without mutating, so tests can check
+    /// state on chain (tag 70)
+    AssertEquals(Counter),
+    /// Decrement, and once the counter reaches zero close the account, sending its lamports
+    /// to the second account. The greeted account must sign, and going below zero is an
+    /// error (tag 71)
+    #[cfg(feature = "multi-account")]
+    DecrementAndMaybeClose,
+    /// Set the counter to the little endian counter found at `offset` in the second account,
+    /// which must be owned by `owner`. The payload is the u32 offset then the owner (tag 72)
+    #[cfg(feature = "multi-account")]
+    SetFromOracle { offset: u32, owner: Pubkey },
+    /// Set the counter, but only if the greeted account's data is `expected_len` bytes long.
+    /// The payload is the u32 length then the value (tag 75)
+    #[cfg(feature = "arithmetic-ext")]
+    SetChecked { expected_len: u32, value: Counter },
+    /// Increment and log a memo saying why, the rest of the payload is the memo in UTF-8,
+    /// at most `MAX_MEMO_LEN` bytes (tag 80)
+    #[cfg(feature = "arithmetic-ext")]
+    IncrementWithMemo(String),
+    /// Return the id the program is running as, for checking CPI routing (tag 83)
+    WhoAmI,
+    /// Store a commitment to the next value, see `commitment_hash` (tag 85)
+    #[cfg(feature = "governance")]
+    Commit([u8; 32]),
+    /// Set the counter to `value` if it and `salt` hash to the stored commitment, which is
+    /// used up. The payload is the value then the 32 byte salt (tag 86)
+    #[cfg(feature = "governance")]
+    Reveal { value: Counter, salt: [u8; 32] },
+    /// Return the counters of every account passed, in order, as packed little endian
+    /// counters. At most `MAX_GET_MANY` accounts fit in the return data (tag 87)
+    #[cfg(feature = "multi-account")]
+    GetMany,
+    /// Set the counter to the `checksum` of the second account's full data, so the greeted
+    /// account holds a fingerprint of it (tag 90)
+    #[cfg(feature = "multi-account")]
+    SetToChecksum,
+    /// Multiply the counter by the sample, keeping a running product. The first sample on an
+    /// account starts the product from 1. A product the counter can't hold is an error, not
+    /// a wrap (tag 92)
+    #[cfg(feature = "arithmetic-ext")]
+    MulSample(u32),
+    /// Set the counter, but only while the clock's unix timestamp is within `start..=end`.
+    /// If the clock can't be read the set is skipped with a warning, or fails with the
+    /// `strict-clock` feature. The payload is the value then the two i64 bounds (tag 93)
+    #[cfg(feature = "governance")]
+    SetInWindow { value: Counter, start: i64, end: i64 },
+    /// Pick what every mutation writes to return data, one of the `VERBOSITY_*` levels.
+    /// The payload is the one byte level (tag 96)
+    #[cfg(feature = "governance")]
+    SetVerbosity(u8),
+    /// Add `delta`, then fail without writing anything unless the counter is now `expected`.
+    /// The payload is the delta then the expected counter (tag 97)
+    #[cfg(feature = "arithmetic-ext")]
+    AddThenAssert { delta: Counter, expected: Counter },
+    /// Allow or forbid MulSample and AdjustPercent taking a non zero counter to zero.
+    /// The payload is one byte, 1 to allow and 0 to forbid (tag 98)
+    #[cfg(feature = "governance")]
+    SetAllowZeroResult(bool),
+}
+
+/// Decodes one variant from everything that follows its tag byte, each decoder is
+/// responsible for checking that its own payload has the right length
+type Decoder = fn(&[u8]) -> Result<HelloInstruction, ProgramError>;
+
+/// Routing table from tag to decoder, adding an instruction means adding a row here. Every
+/// tag check goes through `Tag`, which looks here
+const DECODERS: &[(u8, Decoder)] = &[
+    (0, decode_increment),
+    (1, decode_decrement),
+    (2, decode_set),
+    #[cfg(feature = "multi-account")]
+    (49, decode_snapshot),
+    #[cfg(feature = "multi-account")]
+    (52, decode_merge_average),
+    #[cfg(feature = "governance")]
+    (57, decode_set_monotonic),
+    #[cfg(feature = "debug-export")]
+    (58, decode_export_hex),
+    #[cfg(feature = "arithmetic-ext")]
+    (59, decode_adjust_percent),
+    #[cfg(feature = "arithmetic-ext")]
+    (60, decode_increment_if_equal),
+    (61, decode_verify_checksum),
+    (64, decode_get_data_len),
+    #[cfg(feature = "arithmetic-ext")]
+    (66, decode_add_many),
+    (70, decode_assert_equals),
+    #[cfg(feature = "multi-account")]
+    (71, decode_decrement_and_maybe_close),
+    #[cfg(feature = "multi-account")]
+    (72, decode_set_from_oracle),
+    #[cfg(feature = "arithmetic-ext")]
+    (75, decode_set_checked),
+    #[cfg(feature = "arithmetic-ext")]
+    (80, decode_increment_with_memo),
+    (83, decode_who_am_i),
+    #[cfg(feature = "governance")]
+    (85, decode_commit),
+    #[cfg(feature = "governance")]
+    (86, decode_reveal),
+    #[cfg(feature = "multi-account")]
+    (87, decode_get_many),
+    #[cfg(feature = "multi-account")]
+    (90, decode_set_to_checksum),
+    #[cfg(feature = "arithmetic-ext")]
+    (92, decode_mul_sample),
+    #[cfg(feature = "governance")]
+    (93, decode_set_in_window),
+    #[cfg(feature = "governance")]
+    (96, decode_set_verbosity),
+    #[cfg(feature = "arithmetic-ext")]
+    (97, decode_add_then_assert),
+    #[cfg(feature = "governance")]
+    (98, decode_set_allow_zero_result),
+];
+
+/// An instruction tag this build has a decoder for, the only way to get one is `try_from`,
+/// which is what `unpack` routes through
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tag {
+    //position of the tag's row in DECODERS
+    row: usize,
+}
+
+impl TryFrom<u8> for Tag {
+    type Error = ProgramError;
+
+    fn try_from(tag: u8) -> Result<Self, ProgramError> {
+        DECODERS
+            .iter()
+            .position(|(registered, _)| *registered == tag)
+            .map(|row| Tag { row })
+            .ok_or(ProgramError::InvalidInstructionData)
+    }
+}
+
+impl From<Tag> for u8 {
+    fn from(tag: Tag) -> u8 {
+        DECODERS[tag.row].0
+    }
+}
+
+impl Tag {
+    /// Decode `rest`, the bytes after the tag, as this tag's instruction
+    pub fn decode(self, rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+        let (_, decode) = DECODERS[self.row];
+        decode(rest)
+    }
+}
+
+/// Tag reserved for the versioned envelope `[254, format_version, ...]`, which lets future
+/// instruction layouts coexist with the current one, the version picks the parser
+pub const VERSIONED_ENVELOPE_TAG: u8 = 254;
+
+/// Longest memo `IncrementWithMemo` accepts, in bytes, which bounds what it costs to log
+pub const MAX_MEMO_LEN: usize = 128;
+
+/// Most deltas a single `AddMany` may carry, which bounds the compute it can use
+pub const MAX_BATCH: usize = 128;
+
+/// Most accounts a single `GetMany` can read, as many counters as fit in the return data
+pub const MAX_GET_MANY: usize = MAX_RETURN_DATA / mem::size_of::<Counter>();
+
+/// Offset added to every tag on the wire, envelope and inner tags included. The tags here
+/// and in the schema are relative to it. A program embedding this one can move the whole
+/// namespace out of its own tags' way with `pack_with_base` and `unpack_with_base`
+pub const TAG_BASE: u8 = 0;
+
+//the schema text is shared between counter types, only the argument type differs. concat!
+//only takes literals, so the memo and verbosity bounds are repeated here and a test checks
+//them against MAX_MEMO_LEN and VERBOSITY_FULL
+macro_rules! instruction_schema {
+    ($counter:literal) => {
+        concat!(
+            r#"{
+  "encoding": "tag:u8 followed by args, little endian",
+  "instructions": [
+    { "tag": 0, "name": "Increment", "args": [] },
+    { "tag": 1, "name": "Decrement", "args": [] },
+    { "tag": 2, "name": "Set", "args": [{ "name": "value", "type": ""#,
+            $counter,
+            r#"" }] },
+    { "tag": 49, "name": "Snapshot", "feature": "multi-account", "args": [] },
+    { "tag": 52, "name": "MergeAverage", "feature": "multi-account", "args": [] },
+    { "tag": 57, "name": "SetMonotonic", "feature": "governance", "args": [] },
+    { "tag": 58, "name": "ExportHex", "feature": "debug-export", "args": [] },
+    { "tag": 59, "name": "AdjustPercent", "feature": "arithmetic-ext", "args": [{ "name": "percent", "type": "i32" }] },
+    { "tag": 60, "name": "IncrementIfEqual", "feature": "arithmetic-ext", "args": [{ "name": "expected", "type": ""#,
+            $counter,
+            r#"" }] },
+    { "tag": 61, "name": "VerifyChecksum", "args": [{ "name": "checksum", "type": "u32" }] },
+    { "tag": 64, "name": "GetDataLen", "args": [] },
+    { "tag": 66, "name": "AddMany", "feature": "arithmetic-ext", "args": [{ "name": "deltas", "type": "vec<"#,
+            $counter,
+            r#">", "length": "shortvec" }] },
+    { "tag": 70, "name": "AssertEquals", "args": [{ "name": "expected", "type": ""#,
+            $counter,
+            r#"" }] },
+    { "tag": 71, "name": "DecrementAndMaybeClose", "feature": "multi-account", "args": [] },
+    { "tag": 72, "name": "SetFromOracle", "feature": "multi-account", "args": [{ "name": "offset", "type": "u32" }, { "name": "owner", "type": "pubkey" }] },
+    { "tag": 75, "name": "SetChecked", "feature": "arithmetic-ext", "args": [{ "name": "expected_len", "type": "u32" }, { "name": "value", "type": ""#,
+            $counter,
+            r#"" }] },
+    { "tag": 80, "name": "IncrementWithMemo", "feature": "arithmetic-ext", "args": [{ "name": "memo", "type": "utf8", "max_len": 128 }] },
+    { "tag": 83, "name": "WhoAmI", "args": [] },
+    { "tag": 85, "name": "Commit", "feature": "governance", "args": [{ "name": "hash", "type": "[u8; 32]" }] },
+    { "tag": 86, "name": "Reveal", "feature": "governance", "args": [{ "name": "value", "type": ""#,
+            $counter,
+            r#"" }, { "name": "salt", "type": "[u8; 32]" }] },
+    { "tag": 87, "name": "GetMany", "feature": "multi-account", "args": [] },
+    { "tag": 90, "name": "SetToChecksum", "feature": "multi-account", "args": [] },
+    { "tag": 92, "name": "MulSample", "feature": "arithmetic-ext", "args": [{ "name": "sample", "type": "u32" }] },
+    { "tag": 93, "name": "SetInWindow", "feature": "governance", "args": [{ "name": "value", "type": ""#,
+            $counter,
+            r#"" }, { "name": "start", "type": "i64" }, { "name": "end", "type": "i64" }] },
+    { "tag": 96, "name": "SetVerbosity", "feature": "governance", "args": [{ "name": "level", "type": "u8", "max": 2 }] },
+    { "tag": 97, "name": "AddThenAssert", "feature": "arithmetic-ext", "args": [{ "name": "delta", "type": ""#,
+            $counter,
+            r#"" }, { "name": "expected", "type": ""#,
+            $counter,
+            r#"" }] },
+    { "tag": 98, "name": "SetAllowZeroResult", "feature": "governance", "args": [{ "name": "allow", "type": "bool" }] }
+  ],
+  "envelope": {
+    "tag": 254,
+    "args": [{ "name": "format_version", "type": "u8" }],
+    "versions": { "1": "tag:u8 followed by args, as above" }
+  }
+}"#
+        )
+    };
+}
+
+/// Machine readable description of the wire format, for tooling that generates client
+/// bindings. Every instruction is a one byte tag followed by its arguments, packed back
+/// to back with integers in little endian. Keep this in sync with `DECODERS`
+#[cfg(not(feature = "signed-counter"))]
+const INSTRUCTION_SCHEMA: &str = instruction_schema!("u32");
+#[cfg(feature = "signed-counter")]
+const INSTRUCTION_SCHEMA: &str = instruction_schema!("i32");
+
+/// JSON schema of every instruction's tag, name and argument layout
+pub fn instruction_schema() -> &'static str {
+    INSTRUCTION_SCHEMA
 }
 
 impl HelloInstruction{
+    /// Number of accounts the instruction expects, the greeted account always comes first
+    pub fn accounts_required(&self) -> usize {
+        match self {
+            HelloInstruction::Increment
+            | HelloInstruction::Decrement
+            | HelloInstruction::Set(_)
+            | HelloInstruction::VerifyChecksum(_)
+            | HelloInstruction::GetDataLen
+            | HelloInstruction::AssertEquals(_)
+            | HelloInstruction::WhoAmI => 1,
+            #[cfg(feature = "debug-export")]
+            HelloInstruction::ExportHex => 1,
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AdjustPercent(_)
+            | HelloInstruction::IncrementIfEqual(_)
+            | HelloInstruction::AddMany(_)
+            | HelloInstruction::SetChecked { .. }
+            | HelloInstruction::IncrementWithMemo(_)
+            | HelloInstruction::MulSample(_)
+            | HelloInstruction::AddThenAssert { .. } => 1,
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetMonotonic
+            | HelloInstruction::Commit(_)
+            | HelloInstruction::Reveal { .. }
+            | HelloInstruction::SetInWindow { .. }
+            | HelloInstruction::SetVerbosity(_)
+            | HelloInstruction::SetAllowZeroResult(_) => 1,
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::Snapshot
+            | HelloInstruction::DecrementAndMaybeClose
+            | HelloInstruction::SetFromOracle { .. }
+            | HelloInstruction::SetToChecksum => 2,
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::MergeAverage => 3,
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::GetMany => 1,
+        }
+    }
+
+    /// Serialize the instruction into the wire format `unpack` reads
+    pub fn pack(&self) -> Vec<u8> {
+        self.pack_with_base(TAG_BASE)
+    }
+
+    /// `pack`, with the tag shifted by `base` instead of `TAG_BASE`
+    pub fn pack_with_base(&self, base: u8) -> Vec<u8> {
+        let mut buf = self.pack_relative();
+        buf[0] = buf[0].wrapping_add(base);
+        buf
+    }
+
+    //the wire format with the tag as listed in DECODERS
+    fn pack_relative(&self) -> Vec<u8> {
+        match self {
+            HelloInstruction::Increment => vec![0],
+            HelloInstruction::Decrement => vec![1],
+            HelloInstruction::Set(value) => {
+                let mut buf = vec![2];
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf
+            }
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::Snapshot => vec![49],
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::MergeAverage => vec![52],
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetMonotonic => vec![57],
+            #[cfg(feature = "debug-export")]
+            HelloInstruction::ExportHex => vec![58],
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AdjustPercent(percent) => {
+                let mut buf = vec![59];
+                buf.extend_from_slice(&percent.to_le_bytes());
+                buf
+            }
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::IncrementIfEqual(expected) => {
+                let mut buf = vec![60];
+                buf.extend_from_slice(&expected.to_le_bytes());
+                buf
+            }
+            HelloInstruction::VerifyChecksum(checksum) => {
+                let mut buf = vec![61];
+                buf.extend_from_slice(&checksum.to_le_bytes());
+                buf
+            }
+            HelloInstruction::GetDataLen => vec![64],
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddMany(deltas) => {
+                let mut buf = vec![66];
+                encode_short_vec_len(deltas.len(), &mut buf);
+                for delta in deltas {
+                    buf.extend_from_slice(&delta.to_le_bytes());
+                }
+                buf
+            }
+            HelloInstruction::AssertEquals(expected) => {
+                let mut buf = vec![70];
+                buf.extend_from_slice(&expected.to_le_bytes());
+                buf
+            }
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::DecrementAndMaybeClose => vec![71],
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::SetFromOracle { offset, owner } => {
+                let mut buf = vec![72];
+                buf.extend_from_slice(&offset.to_le_bytes());
+                buf.extend_from_slice(owner.as_ref());
+                buf
+            }
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::SetChecked { expected_len, value } => {
+                let mut buf = vec![75];
+                buf.extend_from_slice(&expected_len.to_le_bytes());
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf
+            }
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::IncrementWithMemo(memo) => {
+                let mut buf = vec![80];
+                buf.extend_from_slice(memo.as_bytes());
+                buf
+            }
+            HelloInstruction::WhoAmI => vec![83],
+            #[cfg(feature = "governance")]
+            HelloInstruction::Commit(hash) => {
+                let mut buf = vec![85];
+                buf.extend_from_slice(hash);
+                buf
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::Reveal { value, salt } => {
+                let mut buf = vec![86];
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf.extend_from_slice(salt);
+                buf
+            }
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::GetMany => vec![87],
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::SetToChecksum => vec![90],
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::MulSample(sample) => {
+                let mut buf = vec![92];
+                buf.extend_from_slice(&sample.to_le_bytes());
+                buf
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetInWindow { value, start, end } => {
+                let mut buf = vec![93];
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf.extend_from_slice(&start.to_le_bytes());
+                buf.extend_from_slice(&end.to_le_bytes());
+                buf
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetVerbosity(level) => vec![96, *level],
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddThenAssert { delta, expected } => {
+                let mut buf = vec![97];
+                buf.extend_from_slice(&delta.to_le_bytes());
+                buf.extend_from_slice(&expected.to_le_bytes());
+                buf
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetAllowZeroResult(allow) => vec![98, u8::from(*allow)],
+        }
+    }
+
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        HelloInstruction::unpack_with_base(input, TAG_BASE)
+    }
+
+    /// `unpack` for tags shifted by `base` instead of `TAG_BASE`
+    pub fn unpack_with_base(input: &[u8], base: u8) -> Result<Self, ProgramError> {
         //split_first() will seperate the first element form the rest and return both
         //ok_or() converts option to a result value goes to a OK type and none becomes an Err
         //we set the error type tp be invalid instruction data
         let(&tag, rest) = input.split_first().ok_or(ProgramError::InvalidInstructionData)?;
-        
-        match tag{
-            0 => return Ok(HelloInstruction::Increment),
-            1 => return Ok(HelloInstruction::Decrement),
-            2 => {
-                //lets be sure that the rest of the data is actually an array of size 4
-                if rest.len() != 4 {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                //try into will attempt to convert a slice into an array of size 4(in this case bc ; 4)so that size is known at compile time
-                let val: Result<[u8 ; 4], _> = rest[..4].try_into();
-                match val{
-                    Ok(i) => {
-                        //from_le_bytes() will convert 4 little endian bytes into a u32
-                        return Ok(HelloInstruction::Set(u32::from_le_bytes(i)));
-                    },
-                    _ => return Err(ProgramError::InvalidInstructionData)
-                }
-            },
-            _ => Err(ProgramError::InvalidInstructionData)
+
+        let tag = tag.wrapping_sub(base);
+        if tag == VERSIONED_ENVELOPE_TAG {
+            return unpack_versioned(rest, base);
+        }
+        Tag::try_from(tag)?.decode(rest)
+    }
+}
+
+//the first byte of the envelope says which layout the rest of the buffer uses
+fn unpack_versioned(rest: &[u8], base: u8) -> Result<HelloInstruction, ProgramError> {
+    let (&version, body) = rest.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+    match version {
+        //v1 wraps the current layout unchanged, envelopes can't be nested
+        1 => {
+            let (&tag, rest) = body.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+            Tag::try_from(tag.wrapping_sub(base))?.decode(rest)
+        }
+        _ => {
+            log!("Unsupported instruction format version {}", version);
+            Err(ProgramError::InvalidInstructionData)
         }
-    
     }
-}
\ No newline at end of file
+}
+
+//increment and decrement take no payload, legacy-payloads lets old padded buffers through
+fn decode_increment(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_legacy_padding(rest)?;
+    Ok(HelloInstruction::Increment)
+}
+
+fn decode_decrement(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_legacy_padding(rest)?;
+    Ok(HelloInstruction::Decrement)
+}
+
+fn decode_set(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    //the payload is exactly one little endian counter, its length comes from the type itself
+    if rest.len() != mem::size_of::<Counter>() {
+        log!("Set expects {} bytes, got {}", mem::size_of::<Counter>(), rest.len());
+    }
+    let value = take_fixed::<{ mem::size_of::<Counter>() }>(rest)?;
+    Ok(HelloInstruction::Set(Counter::from_le_bytes(value)))
+}
+
+#[cfg(feature = "multi-account")]
+fn decode_snapshot(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::Snapshot)
+}
+
+#[cfg(feature = "multi-account")]
+fn decode_merge_average(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::MergeAverage)
+}
+
+#[cfg(feature = "governance")]
+fn decode_set_monotonic(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::SetMonotonic)
+}
+
+#[cfg(feature = "debug-export")]
+fn decode_export_hex(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::ExportHex)
+}
+
+#[cfg(feature = "arithmetic-ext")]
+fn decode_adjust_percent(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    let percent = take_fixed::<{ mem::size_of::<i32>() }>(rest)?;
+    Ok(HelloInstruction::AdjustPercent(i32::from_le_bytes(percent)))
+}
+
+#[cfg(feature = "arithmetic-ext")]
+fn decode_increment_if_equal(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    let expected = take_fixed::<{ mem::size_of::<Counter>() }>(rest)?;
+    Ok(HelloInstruction::IncrementIfEqual(Counter::from_le_bytes(expected)))
+}
+
+fn decode_verify_checksum(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    let checksum = take_fixed::<{ mem::size_of::<u32>() }>(rest)?;
+    Ok(HelloInstruction::VerifyChecksum(u32::from_le_bytes(checksum)))
+}
+
+fn decode_get_data_len(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::GetDataLen)
+}
+
+#[cfg(feature = "arithmetic-ext")]
+fn decode_add_many(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    let (count, deltas) = take_short_vec_len(rest)?;
+    //checked before anything else, an oversized batch costs no more than a valid one
+    if count > MAX_BATCH {
+        log!("AddMany carries {} deltas, at most {} are allowed", count, MAX_BATCH);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    //the count has to account for every byte after it, a short or padded list is rejected
+    if count.checked_mul(mem::size_of::<Counter>()) != Some(deltas.len()) {
+        log!("AddMany declares {} delta(s) but carries {} byte(s)", count, deltas.len());
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let deltas = deltas
+        .chunks_exact(mem::size_of::<Counter>())
+        .map(|delta| take_fixed(delta).map(Counter::from_le_bytes))
+        .collect::<Result<_, _>>()?;
+    Ok(HelloInstruction::AddMany(deltas))
+}
+
+fn decode_assert_equals(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    let expected = take_fixed::<{ mem::size_of::<Counter>() }>(rest)?;
+    Ok(HelloInstruction::AssertEquals(Counter::from_le_bytes(expected)))
+}
+
+#[cfg(feature = "multi-account")]
+fn decode_decrement_and_maybe_close(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::DecrementAndMaybeClose)
+}
+
+#[cfg(feature = "multi-account")]
+fn decode_set_from_oracle(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    const OFFSET: usize = mem::size_of::<u32>();
+    let payload = take_fixed::<{ OFFSET + mem::size_of::<Pubkey>() }>(rest)?;
+    let (offset, owner) = payload.split_at(OFFSET);
+    Ok(HelloInstruction::SetFromOracle {
+        offset: u32::from_le_bytes(take_fixed(offset)?),
+        owner: Pubkey::new_from_array(take_fixed(owner)?),
+    })
+}
+
+#[cfg(feature = "arithmetic-ext")]
+fn decode_set_checked(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    const LEN: usize = mem::size_of::<u32>();
+    let payload = take_fixed::<{ LEN + mem::size_of::<Counter>() }>(rest)?;
+    let (expected_len, value) = payload.split_at(LEN);
+    Ok(HelloInstruction::SetChecked {
+        expected_len: u32::from_le_bytes(take_fixed(expected_len)?),
+        value: Counter::from_le_bytes(take_fixed(value)?),
+    })
+}
+
+#[cfg(feature = "arithmetic-ext")]
+fn decode_increment_with_memo(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    if rest.len() > MAX_MEMO_LEN {
+        log!("Memo is {} bytes, at most {} are allowed", rest.len(), MAX_MEMO_LEN);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let memo = std::str::from_utf8(rest).map_err(|_| {
+        log!("Memo is not valid UTF-8");
+        ProgramError::InvalidInstructionData
+    })?;
+    Ok(HelloInstruction::IncrementWithMemo(memo.to_string()))
+}
+
+fn decode_who_am_i(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::WhoAmI)
+}
+
+#[cfg(feature = "governance")]
+fn decode_commit(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    Ok(HelloInstruction::Commit(take_fixed(rest)?))
+}
+
+#[cfg(feature = "governance")]
+fn decode_reveal(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    const VALUE: usize = mem::size_of::<Counter>();
+    let payload = take_fixed::<{ VALUE + 32 }>(rest)?;
+    let (value, salt) = payload.split_at(VALUE);
+    Ok(HelloInstruction::Reveal {
+        value: Counter::from_le_bytes(take_fixed(value)?),
+        salt: take_fixed(salt)?,
+    })
+}
+
+#[cfg(feature = "multi-account")]
+fn decode_get_many(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::GetMany)
+}
+
+#[cfg(feature = "multi-account")]
+fn decode_set_to_checksum(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    expect_empty(rest)?;
+    Ok(HelloInstruction::SetToChecksum)
+}
+
+#[cfg(feature = "arithmetic-ext")]
+fn decode_mul_sample(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    let sample = take_fixed::<{ mem::size_of::<u32>() }>(rest)?;
+    Ok(HelloInstruction::MulSample(u32::from_le_bytes(sample)))
+}
+
+#[cfg(feature = "governance")]
+fn decode_set_in_window(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    const VALUE: usize = mem::size_of::<Counter>();
+    const BOUND: usize = mem::size_of::<i64>();
+    let payload = take_fixed::<{ VALUE + 2 * BOUND }>(rest)?;
+    let (value, bounds) = payload.split_at(VALUE);
+    let (start, end) = bounds.split_at(BOUND);
+    Ok(HelloInstruction::SetInWindow {
+        value: Counter::from_le_bytes(take_fixed(value)?),
+        start: i64::from_le_bytes(take_fixed(start)?),
+        end: i64::from_le_bytes(take_fixed(end)?),
+    })
+}
+
+#[cfg(feature = "governance")]
+fn decode_set_verbosity(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    let [level] = take_fixed::<1>(rest)?;
+    if level > VERBOSITY_FULL {
+        log!("Verbosity {} is unknown, the highest level is {}", level, VERBOSITY_FULL);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(HelloInstruction::SetVerbosity(level))
+}
+
+#[cfg(feature = "arithmetic-ext")]
+fn decode_add_then_assert(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    const DELTA: usize = mem::size_of::<Counter>();
+    let payload = take_fixed::<{ 2 * DELTA }>(rest)?;
+    let (delta, expected) = payload.split_at(DELTA);
+    Ok(HelloInstruction::AddThenAssert {
+        delta: Counter::from_le_bytes(take_fixed(delta)?),
+        expected: Counter::from_le_bytes(take_fixed(expected)?),
+    })
+}
+
+#[cfg(feature = "governance")]
+fn decode_set_allow_zero_result(rest: &[u8]) -> Result<HelloInstruction, ProgramError> {
+    match take_fixed::<1>(rest)? {
+        [0] => Ok(HelloInstruction::SetAllowZeroResult(false)),
+        [1] => Ok(HelloInstruction::SetAllowZeroResult(true)),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+/// Write `len` as a shortvec, the compact u16 Solana uses for lengths in transactions:
+/// 7 bits per byte, least significant first, the high bit set on every byte but the last.
+/// A length past u16::MAX just keeps going, and `take_short_vec_len` turns it away
+#[cfg(feature = "arithmetic-ext")]
+fn encode_short_vec_len(len: usize, buf: &mut Vec<u8>) {
+    let mut rem = len;
+    loop {
+        let byte = (rem & 0x7f) as u8;
+        rem >>= 7;
+        if rem == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/// Split a shortvec length off the front of `rest`. Like the runtime, only the canonical
+/// encoding of a u16 is accepted, so a value has exactly one valid byte sequence
+#[cfg(feature = "arithmetic-ext")]
+pub(crate) fn take_short_vec_len(rest: &[u8]) -> Result<(usize, &[u8]), ProgramError> {
+    let mut len = 0usize;
+    for (i, &byte) in rest.iter().enumerate().take(3) {
+        len |= usize::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            //a zero byte after the first only pads the encoding, and the third byte can
+            //carry two more bits at most
+            if (i > 0 && byte == 0) || len > usize::from(u16::MAX) {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            return Ok((len, &rest[i + 1..]));
+        }
+    }
+    Err(ProgramError::InvalidInstructionData)
+}
+
+//for instructions that take no arguments
+fn expect_empty(rest: &[u8]) -> Result<(), ProgramError> {
+    if !rest.is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(())
+}
+
+//the first clients padded Increment/Decrement out to the width of Set, only tolerated
+//when built with legacy-payloads
+#[cfg(feature = "legacy-payloads")]
+fn expect_legacy_padding(_rest: &[u8]) -> Result<(), ProgramError> {
+    Ok(())
+}
+
+#[cfg(not(feature = "legacy-payloads"))]
+fn expect_legacy_padding(rest: &[u8]) -> Result<(), ProgramError> {
+    expect_empty(rest)
+}
+
+/// Read a payload of exactly `N` bytes, numeric decoders pass `size_of` of the type they
+/// decode so a payload of the wrong width is rejected instead of truncated
+pub(crate) fn take_fixed<const N: usize>(rest: &[u8]) -> Result<[u8; N], ProgramError> {
+    //try into will only succeed when the slice length matches N exactly
+    rest.try_into().map_err(|_| ProgramError::InvalidInstructionData)
+}
@@ -1,20 +1,253 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
+    account_info::AccountInfo,
     entrypoint,
     entrypoint::ProgramResult,
-    msg,
-    program_error::ProgramError,
+    hash::hashv,
     pubkey::Pubkey,
 };
+/// `msg!` unless built with the `no-logs` feature, which drops every log line, events
+/// included, to save the compute they cost. Arguments are still type checked either way
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if !cfg!(feature = "no-logs") {
+            solana_program::msg!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "client")]
+pub mod client;
+pub mod error;
 pub mod instruction;
-use crate::instruction::HelloInstruction;
+pub mod processor;
+use crate::processor::Processor;
+
+/// Namespace prepended to every event line so this program's events can be told
+/// apart from other programs sharing the same log stream, e.g. `GREETING:INCREMENT`.
+/// Deployments can override it at build time with the `GREETING_LOG_PREFIX` env var
+pub const LOG_PREFIX: &str = match option_env!("GREETING_LOG_PREFIX") {
+    Some(prefix) => prefix,
+    None => "GREETING",
+};
+
+/// How far Increment and Decrement move the counter, 1 unless the `GREETING_DEFAULT_STEP`
+/// env var is set to a positive integer at build time. Every instruction that moves the
+/// counter by one step uses it, including IncrementIfEqual, IncrementWithMemo and
+/// DecrementAndMaybeClose. There is no `SetStep` yet, so no account stores a step of its
+/// own and this is the only step. A per-account step set by a `SetStep` would win over this
+/// one. It would take its bytes from the reserved space, where an account that never ran
+/// `SetStep` reads zero, and a zero step there has to mean "use `DEFAULT_STEP`"
+pub const DEFAULT_STEP: Counter = match option_env!("GREETING_DEFAULT_STEP") {
+    Some(step) => parse_step(step),
+    None => 1,
+};
+
+//const parser for DEFAULT_STEP, anything but a positive decimal fails the build
+const fn parse_step(step: &str) -> Counter {
+    let digits = step.as_bytes();
+    assert!(!digits.is_empty(), "GREETING_DEFAULT_STEP is empty");
+    let mut value: Counter = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        let digit = digits[i];
+        assert!(digit.is_ascii_digit(), "GREETING_DEFAULT_STEP must be a decimal integer");
+        value = value * 10 + (digit - b'0') as Counter;
+        i += 1;
+    }
+    assert!(value > 0, "GREETING_DEFAULT_STEP must be positive");
+    value
+}
+
+/// Integer type backing the counter, an unsigned u32 by default. Payload lengths, the
+/// account layout and the checked arithmetic all derive from it. Off-chain, the type names
+/// in the instruction schema and in `hello_world.ts` have to be changed alongside it
+#[cfg(not(feature = "signed-counter"))]
+pub type Counter = u32;
+
+/// Integer type backing the counter, the `signed-counter` feature makes it an i32 so
+/// decrements can go below zero and `Set` accepts negative values
+#[cfg(feature = "signed-counter")]
+pub type Counter = i32;
 
 /// Define the type of state stored in accounts
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub struct GreetingAccount {
     /// number of greetings
-    pub counter: u32,
+    pub counter: Counter,
+    /// once set, any instruction that would lower the counter is rejected
+    pub monotonic: bool,
+    /// who made the last change, the first signer passed or the account itself if none signed
+    pub last_writer: Pubkey,
+    /// number of changes ever written, consumers of the event log use it to spot gaps
+    pub op_count: u64,
+    /// the hash committed to by the last Commit, all zero when there is nothing to reveal
+    pub commitment: [u8; COMMITMENT_LEN],
+    /// `fields_checksum` as of the last write, so state edited outside the program can be
+    /// told apart
+    pub checksum: u32,
+    /// what mutations write to return data, one of the `VERBOSITY_*` levels. It takes the
+    /// first reserved byte, which accounts written before it hold as `VERBOSITY_TRANSITION`
+    pub return_verbosity: u8,
+    /// whether MulSample and AdjustPercent may take a non zero counter to zero, in the next
+    /// reserved byte. New accounts allow it, the program reads the zeroed data of an account
+    /// it has never written as allowing too
+    pub allow_zero_result: bool,
+    /// set by the first MulSample, which multiplies 1 rather than whatever the counter held,
+    /// so a running product on a fresh account doesn't stay at zero. Later samples multiply
+    /// the counter as it stands. Takes the reserved byte after `allow_zero_result`
+    pub product_started: bool,
+    /// always zero, future fields are carved out of this space so adding one doesn't change
+    /// the account size or force existing accounts to be reallocated
+    _reserved: [u8; RESERVED_LEN],
+}
+
+impl Default for GreetingAccount {
+    fn default() -> Self {
+        GreetingAccount {
+            counter: 0,
+            monotonic: false,
+            last_writer: Pubkey::default(),
+            op_count: 0,
+            commitment: [0; COMMITMENT_LEN],
+            checksum: 0,
+            return_verbosity: VERBOSITY_TRANSITION,
+            allow_zero_result: true,
+            product_started: false,
+            _reserved: [0; RESERVED_LEN],
+        }
+    }
+}
+
+/// Chainable way to fill in a `GreetingAccount`, e.g.
+/// `GreetingAccount::builder().counter(5).monotonic(true).build()`. Unset fields keep their
+/// defaults and `build` fills in the matching checksum
+#[derive(Debug, Default)]
+pub struct GreetingAccountBuilder {
+    state: GreetingAccount,
+}
+
+impl GreetingAccountBuilder {
+    pub fn counter(mut self, counter: Counter) -> Self {
+        self.state.counter = counter;
+        self
+    }
+
+    pub fn monotonic(mut self, monotonic: bool) -> Self {
+        self.state.monotonic = monotonic;
+        self
+    }
+
+    pub fn last_writer(mut self, last_writer: Pubkey) -> Self {
+        self.state.last_writer = last_writer;
+        self
+    }
+
+    pub fn op_count(mut self, op_count: u64) -> Self {
+        self.state.op_count = op_count;
+        self
+    }
+
+    pub fn commitment(mut self, commitment: [u8; COMMITMENT_LEN]) -> Self {
+        self.state.commitment = commitment;
+        self
+    }
+
+    pub fn return_verbosity(mut self, return_verbosity: u8) -> Self {
+        self.state.return_verbosity = return_verbosity;
+        self
+    }
+
+    pub fn allow_zero_result(mut self, allow_zero_result: bool) -> Self {
+        self.state.allow_zero_result = allow_zero_result;
+        self
+    }
+
+    pub fn product_started(mut self, product_started: bool) -> Self {
+        self.state.product_started = product_started;
+        self
+    }
+
+    /// The state as set, sealed with its checksum the way the program would store it
+    pub fn build(self) -> GreetingAccount {
+        let mut state = self.state;
+        state.checksum = state.fields_checksum().expect("serializing into a Vec can't fail");
+        state
+    }
+}
+
+/// Bytes still free at the end of a `GreetingAccount`, a new field shrinks this by its size
+/// so `GreetingAccount::LEN` stays the same
+const RESERVED_LEN: usize = 29;
+
+/// Size of a commitment hash, the whole sha256 is stored so a Reveal is checked against
+/// every bit that was committed to
+pub const COMMITMENT_LEN: usize = 32;
+
+/// Mutations return the previous and the new counter, two little endian counters. This is
+/// the level of a new account
+pub const VERBOSITY_TRANSITION: u8 = 0;
+
+/// Mutations return only the new counter
+pub const VERBOSITY_COUNTER: u8 = 1;
+
+/// Mutations return the previous and new counter followed by the u64 `op_count` and the
+/// `last_writer` key, as they were stored
+pub const VERBOSITY_FULL: u8 = 2;
+
+/// Hash to pass to Commit before revealing `value` with `salt`, the sha256 of the little
+/// endian value followed by the salt
+pub fn commitment_hash(value: Counter, salt: &[u8; 32]) -> [u8; 32] {
+    hashv(&[&value.to_le_bytes(), salt]).to_bytes()
+}
+
+impl GreetingAccount {
+    /// Start building a state from all zero fields
+    pub fn builder() -> GreetingAccountBuilder {
+        GreetingAccountBuilder::default()
+    }
+
+    /// Serialized size of the state, the data length to allocate for a greeting account.
+    /// Accounts created before the commitment grew to the full hash, or before the reserved
+    /// space came back, are short of this and no longer load. They have to be created again
+    /// at this size, later fields come out of the reserved space and keep it as it is
+    pub const LEN: usize = std::mem::size_of::<Counter>()
+        + 1
+        + 32
+        + 8
+        + COMMITMENT_LEN
+        + std::mem::size_of::<u32>()
+        + 1
+        + 1
+        + 1
+        + RESERVED_LEN;
+
+    /// `checksum` of the serialized state with `checksum` itself zeroed, what the program
+    /// stores in `checksum` on every write. An all zero account checks out as it is
+    pub fn fields_checksum(&self) -> std::io::Result<u32> {
+        let unsealed = GreetingAccount {
+            checksum: 0,
+            ..self.clone()
+        };
+        Ok(checksum(&unsealed.try_to_vec()?))
+    }
+
+    /// Zero the reserved space before writing the account back
+    pub fn clear_reserved(&mut self) {
+        self._reserved = [0; RESERVED_LEN];
+    }
+}
+
+/// Cheap, non cryptographic checksum of some bytes: the wrapping sum of them read as
+/// little endian u32 words, the last word zero padded. The words are cut from the bytes
+/// as they come, so for a serialized `GreetingAccount` most of them straddle two fields and
+/// this is not a sum of the field values
+pub fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_le_bytes(word))
+    })
 }
 
 // Declare and export the program's entrypoint
@@ -26,64 +259,112 @@ pub fn process_instruction(
     accounts: &[AccountInfo], // The account to say hello to
     instruction_data: &[u8], // Ignored, all helloworld instructions are hellos
 ) -> ProgramResult {
+    //the resulting counter is only interesting to internal callers, the runtime just needs success
+    Processor::process(program_id, accounts, instruction_data).map(|_| ())
+}
 
-    let instructions = HelloInstruction::unpack(instruction_data)?;
+// Sanity tests
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::GreetingError;
+    use crate::instruction::HelloInstruction;
+    use solana_program::program_error::ProgramError;
+    use solana_program::clock::{Clock, Epoch};
+    use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+    use std::cell::RefCell;
+    use std::sync::Once;
 
+    thread_local! {
+        //each test runs on its own thread, so captured output never leaks between tests
+        static LOGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+        static RETURN_DATA: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+        //the Clock sysvar, which is unsupported until a test sets it
+        static CLOCK: RefCell<Option<Clock>> = const { RefCell::new(None) };
+    }
 
-    msg!("Hello World Rust program entrypoint");
+    //syscall stubs that record what the program logs and returns instead of dropping it
+    struct TestSyscallStubs;
 
-    // Iterating accounts is safer than indexing
-    let accounts_iter = &mut accounts.iter();
+    impl SyscallStubs for TestSyscallStubs {
+        fn sol_log(&self, message: &str) {
+            LOGS.with(|logs| logs.borrow_mut().push(message.to_string()));
+        }
 
-    // Get the account to say hello to
-    let account = next_account_info(accounts_iter)?;
+        fn sol_set_return_data(&mut self, data: &[u8]) {
+            RETURN_DATA.with(|return_data| *return_data.borrow_mut() = Some(data.to_vec()));
+        }
 
-    // The account must be owned by the program in order to modify its data
-    if account.owner != program_id {
-        msg!("Greeted account does not have the correct program id");
-        return Err(ProgramError::IncorrectProgramId);
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            CLOCK.with(|clock| match &*clock.borrow() {
+                Some(clock) => {
+                    //the caller passes a pointer to a Clock for the sysvar to be written into
+                    unsafe { *(var_addr as *mut Clock) = clock.clone() };
+                    solana_program::entrypoint::SUCCESS
+                }
+                None => solana_program::program_error::UNSUPPORTED_SYSVAR,
+            })
+        }
     }
 
-    // Increment and store the number of times the account has been greeted
-    let mut greeting_account = GreetingAccount::try_from_slice(&account.data.borrow())?;
-    
-    match instructions{
-        HelloInstruction::Increment => greeting_account.counter += 1,
-        HelloInstruction::Decrement => greeting_account.counter -= 1,
-        HelloInstruction::Set(x) => greeting_account.counter = x,
-    }
+    static INSTALL_STUBS: Once = Once::new();
 
-    greeting_account.serialize(&mut &mut account.data.borrow_mut()[..])?;
+    fn install_test_stubs() {
+        INSTALL_STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(TestSyscallStubs));
+        });
+    }
 
-    msg!("Greeted {} time(s)!", greeting_account.counter);
+    //returns everything logged on this thread since the last call
+    fn take_logs() -> Vec<String> {
+        LOGS.with(|logs| logs.borrow_mut().drain(..).collect())
+    }
 
-    Ok(())
-}
+    //serialized state of an otherwise default account holding `counter`, checksum included
+    fn account_data(counter: Counter) -> Vec<u8> {
+        GreetingAccount::builder().counter(counter).build().try_to_vec().unwrap()
+    }
 
-// Sanity tests
-#[cfg(test)]
-mod test {
-    use super::*;
-    use solana_program::clock::Epoch;
-    use std::mem;
+    //makes the Clock sysvar read `unix_timestamp` on this thread
+    #[cfg(feature = "governance")]
+    fn set_unix_timestamp(unix_timestamp: i64) {
+        CLOCK.with(|clock| {
+            *clock.borrow_mut() = Some(Clock {
+                unix_timestamp,
+                ..Clock::default()
+            })
+        });
+    }
 
-    #[test]
-    fn test_sanity() {
-        let program_id = Pubkey::default();
-        let key = Pubkey::default();
-        let mut lamports = 0;
-        let mut data = vec![0; mem::size_of::<u32>()];
-        let owner = Pubkey::default();
-        let account = AccountInfo::new(
-            &key,
+    //a writable account at the default key, owned by the default program id and holding
+    //`data`. Its parts are leaked so the account can outlive the test's locals
+    fn greeting_account_info(data: Vec<u8>) -> AccountInfo<'static> {
+        AccountInfo::new(
+            Box::leak(Box::new(Pubkey::default())),
             false,
             true,
-            &mut lamports,
-            &mut data,
-            &owner,
+            Box::leak(Box::new(0)),
+            Box::leak(data.into_boxed_slice()),
+            Box::leak(Box::new(Pubkey::default())),
             false,
             Epoch::default(),
-        );
+        )
+    }
+
+    //returns and clears the return data set on this thread
+    fn take_return_data() -> Option<Vec<u8>> {
+        RETURN_DATA.with(|return_data| return_data.borrow_mut().take())
+    }
+
+    //two little endian counters back to back, as mutations and overflows leave in return data
+    fn counter_pair(first: Counter, second: Counter) -> Vec<u8> {
+        [first.to_le_bytes(), second.to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn test_sanity() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(vec![0; GreetingAccount::LEN]);
         //0 - increment
         //1 - decrement
         //2 - set
@@ -95,9 +376,7 @@ mod test {
         //this will create an array of length 5 with each value set to 2
         let mut instruction_data = [2; 5];
         //we keep the first one as 2, the set instruction, the rest is the 100 for what to set it to
-        for i in 1..5{
-            instruction_data[i] = arr[i - 1];
-        }
+        instruction_data[1..5].copy_from_slice(&arr);
         //^[2, 100, 0, 0, 0]
 
 
@@ -118,76 +397,2119 @@ mod test {
                 .counter,
             100
         );
-        //here we set the instrucctions to be all 0 and now it is an increment instruction
-        let instruction_data = [0; 5];
+        //a lone 0 is an increment instruction
+        let instruction_data = [0];
         process_instruction(&program_id, &accounts, &instruction_data).unwrap();
         assert_eq!(
             GreetingAccount::try_from_slice(&accounts[0].data.borrow())
                 .unwrap()
                 .counter,
-            101
+            100 + DEFAULT_STEP
         );
     }
     #[test]
-    #[should_panic]
-    fn test_sub_from_zero() {
+    fn test_decrement_from_zero_errors() {
         let program_id = Pubkey::default();
-        let key = Pubkey::default();
-        let mut lamports = 0;
-        let mut data = vec![0; mem::size_of::<u32>()];
-        let owner = Pubkey::default();
-        let account = AccountInfo::new(
-            &key,
-            false,
-            true,
-            &mut lamports,
-            &mut data,
-            &owner,
-            false,
-            Epoch::default(),
+        //the lowest counter there is, zero unless built with signed-counter
+        let account = greeting_account_info(account_data(Counter::MIN));
+        let accounts = vec![account];
+
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &[1]),
+            Err(GreetingError::Underflow.into())
+        );
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
+                .unwrap()
+                .counter,
+            Counter::MIN
         );
-        //0 - increment
-        //1 - decrement
-        //2 - set
-        //  1-4 -> u32 little endian
-        // [2, 100, 0, 0, 0]
 
-        //this will return an array of u8 of length 2
-        let arr = u32::to_le_bytes(100);
-        //this will create an array of length 5 with each value set to 2
-        let mut instruction_data = [1; 5];
-        //we keep the first one as 2, the set instruction, the rest is the 100 for what to set it to
-        for i in 1..5{
-            instruction_data[i] = arr[i - 1];
-        }
-        //^[2, 100, 0, 0, 0]
+        //the account is still usable afterwards
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
+                .unwrap()
+                .counter,
+            Counter::MIN + DEFAULT_STEP
+        );
+    }
 
+    #[test]
+    #[cfg(not(feature = "no-logs"))]
+    fn test_events_use_log_prefix() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(vec![0; GreetingAccount::LEN]);
+        let accounts = vec![account];
 
-        
+        take_logs();
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        let logs = take_logs();
+        assert!(logs.contains(&format!("{}:INCREMENT counter={}", LOG_PREFIX, DEFAULT_STEP)));
+    }
 
+    #[test]
+    fn test_increment_overflow_reports_headroom() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(Counter::MAX));
         let accounts = vec![account];
 
+        take_return_data();
         assert_eq!(
-            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
-                .unwrap()
-                .counter,
-            0
+            process_instruction(&program_id, &accounts, &[0]),
+            Err(GreetingError::Overflow.into())
         );
-        process_instruction(&program_id, &accounts, &instruction_data).unwrap();
+        //requested delta of one step, no headroom left
+        assert_eq!(take_return_data(), Some(counter_pair(DEFAULT_STEP, 0)));
         assert_eq!(
             GreetingAccount::try_from_slice(&accounts[0].data.borrow())
                 .unwrap()
                 .counter,
-            100
+            Counter::MAX
         );
-        //here we set the instrucctions to be all 0 and now it is an increment instruction
-        let instruction_data = [0; 5];
-        process_instruction(&program_id, &accounts, &instruction_data).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_snapshot_copies_state() {
+        let program_id = Pubkey::default();
+        let source_key = Pubkey::new_unique();
+        let mut source_lamports = 0;
+        let mut source_data = account_data(42);
+        let destination_key = Pubkey::new_unique();
+        let mut destination_lamports = 0;
+        let mut destination_data = vec![0; GreetingAccount::LEN];
+        let owner = Pubkey::default();
+        let source = AccountInfo::new(
+            &source_key,
+            false,
+            true,
+            &mut source_lamports,
+            &mut source_data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        let destination = AccountInfo::new(
+            &destination_key,
+            false,
+            true,
+            &mut destination_lamports,
+            &mut destination_data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        let accounts = vec![source, destination];
+
+        process_instruction(&program_id, &accounts, &[49]).unwrap();
+        assert_eq!(*accounts[1].data.borrow(), *accounts[0].data.borrow());
         assert_eq!(
             GreetingAccount::try_from_slice(&accounts[0].data.borrow())
                 .unwrap()
                 .counter,
-            101
+            42
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_snapshot_rejects_undersized_destination() {
+        let program_id = Pubkey::default();
+        let source_key = Pubkey::new_unique();
+        let mut source_lamports = 0;
+        let mut source_data = account_data(42);
+        let destination_key = Pubkey::new_unique();
+        let mut destination_lamports = 0;
+        let mut destination_data = vec![0; GreetingAccount::LEN - 1];
+        let owner = Pubkey::default();
+        let source = AccountInfo::new(
+            &source_key,
+            false,
+            true,
+            &mut source_lamports,
+            &mut source_data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        let destination = AccountInfo::new(
+            &destination_key,
+            false,
+            true,
+            &mut destination_lamports,
+            &mut destination_data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        let accounts = vec![source, destination];
+
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &[49]),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        assert_eq!(*accounts[1].data.borrow(), vec![0; GreetingAccount::LEN - 1]);
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_snapshot_requires_two_accounts() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(vec![0; GreetingAccount::LEN]);
+        let accounts = vec![account];
+
+        take_logs();
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &[49]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        #[cfg(not(feature = "no-logs"))]
+        assert!(take_logs().contains(&"Expected 2 account(s) but only 1 were passed".to_string()));
+    }
+
+    #[test]
+    fn test_unpack_registered_tags() {
+        assert_eq!(HelloInstruction::unpack(&[0]), Ok(HelloInstruction::Increment));
+        assert_eq!(HelloInstruction::unpack(&[1]), Ok(HelloInstruction::Decrement));
+        assert_eq!(
+            HelloInstruction::unpack(&[2, 100, 0, 0, 0]),
+            Ok(HelloInstruction::Set(100))
+        );
+        #[cfg(feature = "multi-account")]
+        assert_eq!(HelloInstruction::unpack(&[49]), Ok(HelloInstruction::Snapshot));
+
+        //payload lengths are checked by each decoder
+        assert_eq!(
+            HelloInstruction::unpack(&[2, 100]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            HelloInstruction::unpack(&[64, 0]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    #[test]
+    fn test_unpack_unregistered_tags() {
+        let mut registered = vec![0, 1, 2, 61, 64, 70, 83];
+        if cfg!(feature = "debug-export") {
+            registered.push(58);
+        }
+        if cfg!(feature = "arithmetic-ext") {
+            registered.extend_from_slice(&[59, 60, 66, 75, 80, 92, 97]);
+        }
+        if cfg!(feature = "governance") {
+            registered.extend_from_slice(&[57, 85, 86, 93, 96, 98]);
+        }
+        if cfg!(feature = "multi-account") {
+            registered.extend_from_slice(&[49, 52, 71, 72, 87, 90]);
+        }
+        for tag in (0..=u8::MAX).filter(|tag| !registered.contains(tag)) {
+            assert_eq!(
+                HelloInstruction::unpack(&[tag, 0, 0, 0, 0]),
+                Err(ProgramError::InvalidInstructionData),
+                "tag {} should not decode",
+                tag
+            );
+        }
+        assert_eq!(HelloInstruction::unpack(&[]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn test_take_fixed() {
+        use crate::instruction::take_fixed;
+
+        assert_eq!(take_fixed::<4>(&[1, 2, 3, 4]), Ok([1, 2, 3, 4]));
+        assert_eq!(take_fixed::<4>(&[1, 2, 3]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(take_fixed::<4>(&[1, 2, 3, 4, 5]), Err(ProgramError::InvalidInstructionData));
+
+        assert_eq!(
+            take_fixed::<8>(&u64::MAX.to_le_bytes()),
+            Ok(u64::MAX.to_le_bytes())
+        );
+        assert_eq!(take_fixed::<8>(&[0; 4]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(take_fixed::<8>(&[0; 9]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn test_instruction_schema_lists_every_variant() {
+        let schema = crate::instruction::instruction_schema();
+        let variants = [
+            (0, HelloInstruction::Increment),
+            (1, HelloInstruction::Decrement),
+            (2, HelloInstruction::Set(0)),
+            #[cfg(feature = "multi-account")]
+            (49, HelloInstruction::Snapshot),
+            #[cfg(feature = "multi-account")]
+            (52, HelloInstruction::MergeAverage),
+            #[cfg(feature = "governance")]
+            (57, HelloInstruction::SetMonotonic),
+            #[cfg(feature = "debug-export")]
+            (58, HelloInstruction::ExportHex),
+            #[cfg(feature = "arithmetic-ext")]
+            (59, HelloInstruction::AdjustPercent(0)),
+            #[cfg(feature = "arithmetic-ext")]
+            (60, HelloInstruction::IncrementIfEqual(0)),
+            (61, HelloInstruction::VerifyChecksum(0)),
+            (64, HelloInstruction::GetDataLen),
+            #[cfg(feature = "arithmetic-ext")]
+            (66, HelloInstruction::AddMany(vec![])),
+            (70, HelloInstruction::AssertEquals(0)),
+            #[cfg(feature = "multi-account")]
+            (71, HelloInstruction::DecrementAndMaybeClose),
+            #[cfg(feature = "multi-account")]
+            (72, HelloInstruction::SetFromOracle { offset: 0, owner: Pubkey::default() }),
+            #[cfg(feature = "arithmetic-ext")]
+            (75, HelloInstruction::SetChecked { expected_len: 0, value: 0 }),
+            #[cfg(feature = "arithmetic-ext")]
+            (80, HelloInstruction::IncrementWithMemo(String::new())),
+            (83, HelloInstruction::WhoAmI),
+            #[cfg(feature = "governance")]
+            (85, HelloInstruction::Commit([0; 32])),
+            #[cfg(feature = "governance")]
+            (86, HelloInstruction::Reveal { value: 0, salt: [0; 32] }),
+            #[cfg(feature = "multi-account")]
+            (87, HelloInstruction::GetMany),
+            #[cfg(feature = "multi-account")]
+            (90, HelloInstruction::SetToChecksum),
+            #[cfg(feature = "arithmetic-ext")]
+            (92, HelloInstruction::MulSample(0)),
+            #[cfg(feature = "governance")]
+            (93, HelloInstruction::SetInWindow { value: 0, start: 0, end: 0 }),
+            #[cfg(feature = "governance")]
+            (96, HelloInstruction::SetVerbosity(0)),
+            #[cfg(feature = "arithmetic-ext")]
+            (97, HelloInstruction::AddThenAssert { delta: 0, expected: 0 }),
+            #[cfg(feature = "governance")]
+            (98, HelloInstruction::SetAllowZeroResult(true)),
+        ];
+        for (tag, variant) in variants.iter() {
+            //the debug name of a variant is its name up to any arguments, tuple or struct
+            let debug = format!("{:?}", variant);
+            let name = debug.split(&['(', ' '][..]).next().unwrap();
+            let entry = format!("{{ \"tag\": {}, \"name\": \"{}\"", tag, name);
+            assert!(schema.contains(&entry), "schema is missing {}", name);
+        }
+    }
+
+    #[test]
+    fn test_instruction_schema_bounds_match_constants() {
+        //the schema is a literal, so the limits it spells out are checked against the
+        //constants the decoders enforce
+        let schema = crate::instruction::instruction_schema();
+        let memo = format!(
+            r#""name": "memo", "type": "utf8", "max_len": {} }}"#,
+            instruction::MAX_MEMO_LEN
+        );
+        assert!(schema.contains(&memo), "schema memo bound isn't MAX_MEMO_LEN");
+        let level = format!(r#""name": "level", "type": "u8", "max": {} }}"#, VERBOSITY_FULL);
+        assert!(schema.contains(&level), "schema verbosity bound isn't VERBOSITY_FULL");
+    }
+
+    //runs MergeAverage over two sources holding `a` and `b` and returns the stored result
+    #[cfg(feature = "multi-account")]
+    fn merge_average(a: Counter, b: Counter) -> Result<Counter, ProgramError> {
+        let program_id = Pubkey::default();
+        let owner = Pubkey::default();
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut lamports = [0; 3];
+        let mut data = [
+            vec![0; GreetingAccount::LEN],
+            account_data(a),
+            account_data(b),
+        ];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, &owner, false, Epoch::default())
+            })
+            .collect();
+
+        process_instruction(&program_id, &accounts, &[52])?;
+        let merged = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        Ok(merged.counter)
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_merge_average() {
+        assert_eq!(merge_average(10, 20), Ok(15));
+        //odd sums truncate towards zero
+        assert_eq!(merge_average(10, 21), Ok(15));
+        assert_eq!(merge_average(0, 1), Ok(0));
+        //the intermediate sum would overflow the counter type
+        assert_eq!(merge_average(Counter::MAX, Counter::MAX), Ok(Counter::MAX));
+        assert_eq!(merge_average(Counter::MAX, Counter::MAX - 1), Ok(Counter::MAX - 1));
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_merge_average_requires_program_owned_sources() {
+        let program_id = Pubkey::default();
+        let owner = Pubkey::default();
+        let foreign_owner = Pubkey::new_unique();
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut lamports = [0; 3];
+        let mut data = [
+            vec![0; GreetingAccount::LEN],
+            vec![0; GreetingAccount::LEN],
+            vec![0; GreetingAccount::LEN],
+        ];
+        let owners = [&owner, &owner, &foreign_owner];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .zip(owners.iter())
+            .map(|(((key, lamports), data), owner)| {
+                AccountInfo::new(key, false, true, lamports, data, owner, false, Epoch::default())
+            })
+            .collect();
+
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &[52]),
+            Err(ProgramError::IncorrectProgramId)
+        );
+        assert_eq!(
+            process_instruction(&program_id, &accounts[..2], &[52]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    fn test_unpack_versioned_envelope() {
+        assert_eq!(HelloInstruction::unpack(&[254, 1, 0]), Ok(HelloInstruction::Increment));
+        assert_eq!(
+            HelloInstruction::unpack(&[254, 1, 2, 100, 0, 0, 0]),
+            Ok(HelloInstruction::Set(100))
+        );
+        //the wrapped instruction is still validated
+        assert_eq!(
+            HelloInstruction::unpack(&[254, 1, 2, 100]),
+            Err(ProgramError::InvalidInstructionData)
         );
+        //envelopes don't nest
+        assert_eq!(
+            HelloInstruction::unpack(&[254, 1, 254, 1, 0]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(HelloInstruction::unpack(&[254, 1]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(HelloInstruction::unpack(&[254]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn test_unpack_rejects_unknown_envelope_version() {
+        install_test_stubs();
+        take_logs();
+        assert_eq!(HelloInstruction::unpack(&[254, 2, 0]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(HelloInstruction::unpack(&[254, 0, 0]), Err(ProgramError::InvalidInstructionData));
+        #[cfg(not(feature = "no-logs"))]
+        assert!(take_logs().contains(&"Unsupported instruction format version 2".to_string()));
+    }
+
+    #[test]
+    fn test_processor_returns_resulting_counter() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(vec![0; GreetingAccount::LEN]);
+        let accounts = vec![account];
+
+        assert_eq!(Processor::process(&program_id, &accounts, &[0]), Ok(DEFAULT_STEP));
+        let set = HelloInstruction::Set(10).pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &set), Ok(10));
+        assert_eq!(Processor::process(&program_id, &accounts, &[1]), Ok(10 - DEFAULT_STEP));
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[254, 1, 0]),
+            Ok(10)
+        );
+        //failures still surface the program error
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[3]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "signed-counter")]
+    fn test_signed_decrement_below_zero() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(vec![0; GreetingAccount::LEN]);
+        let accounts = vec![account];
+
+        assert_eq!(Processor::process(&program_id, &accounts, &[1]), Ok(-DEFAULT_STEP));
+        assert_eq!(Processor::process(&program_id, &accounts, &[1]), Ok(-2 * DEFAULT_STEP));
+
+        let mut set_negative = vec![2];
+        set_negative.extend_from_slice(&Counter::to_le_bytes(-100));
+        assert_eq!(Processor::process(&program_id, &accounts, &set_negative), Ok(-100));
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
+                .unwrap()
+                .counter,
+            -100
+        );
+
+        //increments are checked against the signed bound, not the unsigned one
+        let mut set_max = vec![2];
+        set_max.extend_from_slice(&Counter::MAX.to_le_bytes());
+        Processor::process(&program_id, &accounts, &set_max).unwrap();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[0]),
+            Err(GreetingError::Overflow.into())
+        );
+    }
+
+    #[test]
+    fn test_pack_round_trips() {
+        let instructions = [
+            HelloInstruction::Increment,
+            HelloInstruction::Decrement,
+            HelloInstruction::Set(100),
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::Snapshot,
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::MergeAverage,
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetMonotonic,
+            #[cfg(feature = "debug-export")]
+            HelloInstruction::ExportHex,
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AdjustPercent(-10),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::IncrementIfEqual(3),
+            HelloInstruction::VerifyChecksum(u32::MAX),
+            HelloInstruction::GetDataLen,
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddMany(vec![]),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddMany(vec![1, 2, 3]),
+            HelloInstruction::AssertEquals(Counter::MAX),
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::DecrementAndMaybeClose,
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::SetFromOracle { offset: 7, owner: Pubkey::new_unique() },
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::SetChecked { expected_len: 69, value: Counter::MAX },
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::IncrementWithMemo("refill ☕".to_string()),
+            HelloInstruction::WhoAmI,
+            #[cfg(feature = "governance")]
+            HelloInstruction::Commit([5; 32]),
+            #[cfg(feature = "governance")]
+            HelloInstruction::Reveal { value: Counter::MAX, salt: [6; 32] },
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::GetMany,
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::SetToChecksum,
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::MulSample(u32::MAX),
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetInWindow { value: Counter::MAX, start: i64::MIN, end: i64::MAX },
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetVerbosity(VERBOSITY_FULL),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddThenAssert { delta: 2, expected: Counter::MAX },
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetAllowZeroResult(false),
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetAllowZeroResult(true),
+        ];
+        for instruction in instructions.iter() {
+            assert_eq!(HelloInstruction::unpack(&instruction.pack()).as_ref(), Ok(instruction));
+        }
+        assert_eq!(HelloInstruction::Set(100).pack(), vec![2, 100, 0, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "client")]
+    fn test_client_account_metas() {
+        use solana_program::instruction::AccountMeta;
+
+        let program_id = Pubkey::new_unique();
+        let greeted = Pubkey::new_unique();
+
+        let increment = client::increment(&program_id, &greeted);
+        assert_eq!(increment.program_id, program_id);
+        assert_eq!(increment.data, vec![0]);
+        assert_eq!(increment.accounts, vec![AccountMeta::new(greeted, false)]);
+
+        let set = client::set(&program_id, &greeted, 7);
+        assert_eq!(set.data, vec![2, 7, 0, 0, 0]);
+        assert_eq!(set.accounts, vec![AccountMeta::new(greeted, false)]);
+
+        //the source of a snapshot is only read
+        #[cfg(feature = "multi-account")]
+        {
+            let destination = Pubkey::new_unique();
+            let snapshot = client::snapshot(&program_id, &greeted, &destination);
+            assert_eq!(snapshot.data, vec![49]);
+            assert_eq!(
+                snapshot.accounts,
+                vec![
+                    AccountMeta::new_readonly(greeted, false),
+                    AccountMeta::new(destination, false),
+                ]
+            );
+            assert!(snapshot.accounts.iter().all(|meta| !meta.is_signer));
+
+            //closing gives the greeted account's lamports away, so it has to sign
+            let close = client::decrement_and_maybe_close(&program_id, &greeted, &destination);
+            assert_eq!(close.accounts[0], AccountMeta::new(greeted, true));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-logs"))]
+    fn test_set_logs_expected_length() {
+        install_test_stubs();
+        for trailing in [0, 3, 5].iter() {
+            let mut instruction_data = vec![2];
+            instruction_data.resize(1 + trailing, 0);
+
+            take_logs();
+            assert_eq!(
+                HelloInstruction::unpack(&instruction_data),
+                Err(ProgramError::InvalidInstructionData)
+            );
+            assert_eq!(
+                take_logs(),
+                vec![format!(
+                    "Set expects {} bytes, got {}",
+                    std::mem::size_of::<Counter>(),
+                    trailing
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_mutations_return_old_and_new_counter() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(41));
+        let accounts = vec![account];
+
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        assert_eq!(take_return_data(), Some(counter_pair(41, 41 + DEFAULT_STEP)));
+
+        process_instruction(&program_id, &accounts, &HelloInstruction::Set(7).pack()).unwrap();
+        assert_eq!(take_return_data(), Some(counter_pair(41 + DEFAULT_STEP, 7)));
+    }
+
+    #[test]
+    fn test_unit_steps_return_resulting_counter() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(10));
+        let accounts = vec![account];
+        //the new value is the second counter in the return data
+        let resulting = |return_data: Vec<u8>| {
+            Counter::try_from_slice(&return_data[std::mem::size_of::<Counter>()..]).unwrap()
+        };
+
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        assert_eq!(take_return_data().map(resulting), Some(10 + DEFAULT_STEP));
+
+        process_instruction(&program_id, &accounts, &[1]).unwrap();
+        process_instruction(&program_id, &accounts, &[1]).unwrap();
+        assert_eq!(take_return_data().map(resulting), Some(10 - DEFAULT_STEP));
+    }
+
+    #[test]
+    fn test_unit_steps_use_default_step() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(1000));
+        let accounts = vec![account];
+
+        assert_eq!(Processor::process(&program_id, &accounts, &[0]), Ok(1000 + DEFAULT_STEP));
+        assert_eq!(Processor::process(&program_id, &accounts, &[1]), Ok(1000));
+        assert_eq!(Processor::process(&program_id, &accounts, &[1]), Ok(1000 - DEFAULT_STEP));
+    }
+
+    #[test]
+    fn test_parse_step() {
+        assert_eq!(parse_step("1"), 1);
+        assert_eq!(parse_step("250"), 250);
+        assert_eq!(DEFAULT_STEP, option_env!("GREETING_DEFAULT_STEP").map_or(1, parse_step));
+    }
+
+    #[test]
+    #[cfg(feature = "governance")]
+    fn test_monotonic_rejects_lowering() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(10));
+        let accounts = vec![account];
+
+        //lowering is allowed while the flag is off
+        let set = |value| HelloInstruction::Set(value).pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &[1]), Ok(10 - DEFAULT_STEP));
+        assert_eq!(Processor::process(&program_id, &accounts, &set(5)), Ok(5));
+
+        assert_eq!(Processor::process(&program_id, &accounts, &[57]), Ok(5));
+        assert!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
+                .unwrap()
+                .monotonic
+        );
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[1]),
+            Err(GreetingError::NonMonotonic.into())
+        );
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &set(4)),
+            Err(GreetingError::NonMonotonic.into())
+        );
+        //raising, or setting the same value, is still fine
+        assert_eq!(Processor::process(&program_id, &accounts, &set(5)), Ok(5));
+        assert_eq!(Processor::process(&program_id, &accounts, &[0]), Ok(5 + DEFAULT_STEP));
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
+                .unwrap()
+                .counter,
+            5 + DEFAULT_STEP
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "debug-export", feature = "governance", not(feature = "no-logs")))]
+    fn test_export_hex_round_trips() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(42));
+        let accounts = vec![account];
+        process_instruction(&program_id, &accounts, &[57]).unwrap();
+
+        take_logs();
+        process_instruction(&program_id, &accounts, &[58]).unwrap();
+        let marker = format!("{}:EXPORT_HEX ", LOG_PREFIX);
+        let logs = take_logs();
+        let hex = logs
+            .iter()
+            .find_map(|log| log.strip_prefix(&marker))
+            .expect("no hex export logged");
+
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        let expected = GreetingAccount::builder()
+            .counter(42)
+            .monotonic(true)
+            //the SetMonotonic above was the one write
+            .op_count(1)
+            .build();
+        assert_eq!(GreetingAccount::try_from_slice(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    #[cfg(feature = "client")]
+    fn test_client_account_size() {
+        assert_eq!(
+            client::account_size(),
+            GreetingAccount::default().try_to_vec().unwrap().len()
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_client_validate() {
+        assert_eq!(HelloInstruction::validate(&[0]), Ok(()));
+        let set = client::set(&Pubkey::default(), &Pubkey::default(), 9);
+        assert_eq!(HelloInstruction::validate(&set.data), Ok(()));
+        assert_eq!(HelloInstruction::validate(&[]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(HelloInstruction::validate(&[2, 1]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(HelloInstruction::validate(&[200]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    #[cfg(feature = "client")]
+    fn test_client_signed_set_records_signer() {
+        use solana_program::instruction::AccountMeta;
+
+        let program_id = Pubkey::default();
+        let greeted = Pubkey::default();
+        let writer = Pubkey::new_unique();
+        let set = client::signed_by(client::set(&program_id, &greeted, 7), &writer);
+        assert_eq!(
+            set.accounts,
+            vec![AccountMeta::new(greeted, false), AccountMeta::new_readonly(writer, true)]
+        );
+
+        //the accounts exactly as the builder flagged them
+        let (mut greeted_lamports, mut signer_lamports) = (0, 0);
+        let (mut greeted_data, mut signer_data) = (account_data(0), vec![]);
+        let greeted_account = AccountInfo::new(
+            &greeted,
+            set.accounts[0].is_signer,
+            set.accounts[0].is_writable,
+            &mut greeted_lamports,
+            &mut greeted_data,
+            &program_id,
+            false,
+            Epoch::default(),
+        );
+        let signer = AccountInfo::new(
+            &writer,
+            set.accounts[1].is_signer,
+            set.accounts[1].is_writable,
+            &mut signer_lamports,
+            &mut signer_data,
+            &program_id,
+            false,
+            Epoch::default(),
+        );
+        let accounts = vec![greeted_account, signer];
+        assert_eq!(Processor::process(&program_id, &accounts, &set.data), Ok(7));
+        let state = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(state.last_writer, writer);
+
+        //an account the instruction already names just starts signing
+        let own = client::signed_by(client::set(&program_id, &greeted, 8), &greeted);
+        assert_eq!(own.accounts, vec![AccountMeta::new(greeted, true)]);
+    }
+
+    //runs AdjustPercent on an account holding `counter`
+    #[cfg(feature = "arithmetic-ext")]
+    fn adjust_percent(counter: Counter, percent: i32) -> Result<Counter, ProgramError> {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(counter));
+        Processor::process(&program_id, &[account], &HelloInstruction::AdjustPercent(percent).pack())
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_adjust_percent() {
+        assert_eq!(adjust_percent(200, 50), Ok(300));
+        assert_eq!(adjust_percent(200, -50), Ok(100));
+        assert_eq!(adjust_percent(200, -100), Ok(0));
+        //fractions of a unit are truncated
+        assert_eq!(adjust_percent(15, -10), Ok(14));
+        assert_eq!(adjust_percent(15, 10), Ok(16));
+        assert_eq!(
+            adjust_percent(Counter::MAX, i32::MAX),
+            Err(GreetingError::Overflow.into())
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "signed-counter"))]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_adjust_percent_below_zero_errors() {
+        assert_eq!(adjust_percent(200, -101), Err(GreetingError::Underflow.into()));
+    }
+
+    #[test]
+    #[cfg(feature = "client")]
+    fn test_error_codes_round_trip() {
+        let errors = [
+            (GreetingError::Overflow, 100),
+            (GreetingError::NonMonotonic, 101),
+            (GreetingError::Underflow, 102),
+            (GreetingError::CasMismatch, 103),
+            (GreetingError::ChecksumMismatch, 104),
+            (GreetingError::AssertionFailed, 105),
+            (GreetingError::DuplicateAccount, 106),
+            (GreetingError::DataLenMismatch, 107),
+            (GreetingError::WriteVerificationFailed, 108),
+            (GreetingError::CommitmentMismatch, 109),
+            (GreetingError::TooManyAccounts, 110),
+            (GreetingError::OutsideWindow, 111),
+            (GreetingError::StoredChecksumMismatch, 112),
+            (GreetingError::ZeroResultForbidden, 113),
+        ];
+        for (error, code) in errors.iter() {
+            assert_eq!(ProgramError::from(*error), ProgramError::Custom(*code));
+            assert_eq!(GreetingError::from_u32(*code), Some(*error));
+        }
+        assert_eq!(GreetingError::from_u32(0), None);
+        assert_eq!(GreetingError::from_u32(114), None);
+    }
+
+    #[test]
+    fn test_last_writer_tracks_signer() {
+        let program_id = Pubkey::default();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = account_data(0);
+        let owner = Pubkey::default();
+        let signer_key = Pubkey::new_unique();
+        let mut signer_lamports = 0;
+        let mut signer_data = vec![];
+        let account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        //a wallet passed after the greeted account, only there to sign
+        let signer = AccountInfo::new(
+            &signer_key,
+            true,
+            false,
+            &mut signer_lamports,
+            &mut signer_data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        let accounts = vec![account, signer];
+
+        process_instruction(&program_id, &accounts, &HelloInstruction::Set(5).pack()).unwrap();
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
+                .unwrap()
+                .last_writer,
+            signer_key
+        );
+
+        //without a signer the account is credited with its own change
+        process_instruction(&program_id, &accounts[..1], &[0]).unwrap();
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
+                .unwrap()
+                .last_writer,
+            key
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_increment_if_equal() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(7));
+        let accounts = vec![account];
+
+        let increment_if_7 = HelloInstruction::IncrementIfEqual(7).pack();
+        //the same step as a plain Increment
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &increment_if_7),
+            Ok(7 + DEFAULT_STEP)
+        );
+        //the counter moved on, so the same expectation is now stale
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &increment_if_7),
+            Err(GreetingError::CasMismatch.into())
+        );
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow())
+                .unwrap()
+                .counter,
+            7 + DEFAULT_STEP
+        );
+    }
+
+    #[test]
+    fn test_serialized_length_includes_reserved_padding() {
+        //new fields are carved out of the padding, they must not grow the account
+        assert_eq!(GreetingAccount::LEN, 113);
+        let mut state = GreetingAccount::builder().counter(3).build();
+        state._reserved = [0xff; RESERVED_LEN];
+        state.clear_reserved();
+        let bytes = state.try_to_vec().unwrap();
+        assert_eq!(bytes.len(), GreetingAccount::LEN);
+        //the padding is the tail of the state and is written as zeros
+        assert_eq!(bytes[GreetingAccount::LEN - RESERVED_LEN..], [0; RESERVED_LEN]);
+        let zeroed = GreetingAccount::try_from_slice(&[0; GreetingAccount::LEN]).unwrap();
+        assert_eq!(zeroed.fields_checksum().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(7));
+        let accounts = vec![account];
+
+        //a client caches the checksum stored with the state
+        let cached = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().checksum;
+        let verify = HelloInstruction::VerifyChecksum(cached).pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &verify), Ok(7));
+        //the raw bytes include that checksum, so their sum is not what is compared
+        let raw = HelloInstruction::VerifyChecksum(checksum(&accounts[0].data.borrow())).pack();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &raw),
+            Err(GreetingError::ChecksumMismatch.into())
+        );
+
+        //someone else changes the counter, the cached checksum is now stale
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &verify),
+            Err(GreetingError::ChecksumMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_checksum_sums_le_words() {
+        assert_eq!(checksum(&[]), 0);
+        assert_eq!(checksum(&[1, 0, 0, 0, 2, 0, 0, 0]), 3);
+        //a trailing partial word is zero padded
+        assert_eq!(checksum(&[1, 0, 0, 0, 0, 1]), 257);
+        assert_eq!(checksum(&[0xff; 8]), u32::MAX.wrapping_add(u32::MAX));
+    }
+
+    #[test]
+    fn test_five_byte_increment() {
+        //the first clients sent increment padded like a set, [0, 0, 0, 0, 0]
+        let padded = HelloInstruction::unpack(&[0; 5]);
+        #[cfg(feature = "legacy-payloads")]
+        assert_eq!(padded, Ok(HelloInstruction::Increment));
+        #[cfg(not(feature = "legacy-payloads"))]
+        assert_eq!(padded, Err(ProgramError::InvalidInstructionData));
+
+        //the bare tag is accepted either way
+        assert_eq!(HelloInstruction::unpack(&[0]), Ok(HelloInstruction::Increment));
+        assert_eq!(HelloInstruction::unpack(&[1]), Ok(HelloInstruction::Decrement));
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-logs"))]
+    fn test_change_events_carry_increasing_seq() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(0));
+        let accounts = vec![account];
+
+        take_logs();
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        process_instruction(&program_id, &accounts, &HelloInstruction::Set(5).pack()).unwrap();
+        process_instruction(&program_id, &accounts, &[1]).unwrap();
+        let changes: Vec<String> = take_logs()
+            .into_iter()
+            .filter(|line| line.starts_with(&format!("{}:CHANGE", LOG_PREFIX)))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                format!("{}:CHANGE seq=1 counter={}", LOG_PREFIX, DEFAULT_STEP),
+                format!("{}:CHANGE seq=2 counter=5", LOG_PREFIX),
+                format!("{}:CHANGE seq=3 counter={}", LOG_PREFIX, 5 - DEFAULT_STEP),
+            ]
+        );
+
+        //read only instructions don't consume a sequence number
+        process_instruction(&program_id, &accounts, &HelloInstruction::VerifyChecksum(0).pack())
+            .unwrap_err();
+        let state = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(state.op_count, 3);
+    }
+
+    #[test]
+    fn test_fully_populated_account_round_trips() {
+        //every field differs from its default, and from the others, so a swapped or
+        //resized field shows up as a mismatch rather than reading back as zero
+        let state = GreetingAccount {
+            counter: 0x0102_0304,
+            monotonic: true,
+            last_writer: Pubkey::new_from_array([7; 32]),
+            op_count: 0x0a0b_0c0d_0e0f_1011,
+            commitment: [8; COMMITMENT_LEN],
+            return_verbosity: 2,
+            allow_zero_result: false,
+            product_started: true,
+            checksum: 0x1213_1415,
+            ..GreetingAccount::default()
+        };
+        let bytes = state.try_to_vec().unwrap();
+        assert_eq!(bytes.len(), GreetingAccount::LEN);
+
+        let read = GreetingAccount::try_from_slice(&bytes).unwrap();
+        assert_eq!(read.counter, state.counter);
+        assert_eq!(read.monotonic, state.monotonic);
+        assert_eq!(read.last_writer, state.last_writer);
+        assert_eq!(read.op_count, state.op_count);
+        assert_eq!(read.commitment, state.commitment);
+        assert_eq!(read.return_verbosity, state.return_verbosity);
+        assert_eq!(read.allow_zero_result, state.allow_zero_result);
+        assert_eq!(read.product_started, state.product_started);
+        assert_eq!(read.checksum, state.checksum);
+        assert_eq!(read, state);
+    }
+
+    #[test]
+    fn test_get_data_len() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(3));
+        let accounts = vec![account];
+
+        assert_eq!(Processor::process(&program_id, &accounts, &[64]), Ok(3));
+        let expected = accounts[0].data_len() as u64;
+        assert_eq!(take_return_data(), Some(expected.to_le_bytes().to_vec()));
+        assert_eq!(expected, GreetingAccount::LEN as u64);
+        //nothing was written
+        assert_eq!(*accounts[0].data.borrow(), account_data(3));
+
+        //accounts from before the reserved space, or larger than the state, don't deserialize
+        //but can still be asked for their size, which is how a client spots them
+        let mut old = account_data(4);
+        old.truncate(GreetingAccount::LEN - RESERVED_LEN - 2);
+        let mut oversized = account_data(5);
+        oversized.resize(GreetingAccount::LEN + 16, 0);
+        for (data, counter) in [(old, 4), (oversized, 5), (vec![], 0)].iter() {
+            let len = data.len() as u64;
+            assert!(GreetingAccount::try_from_slice(data).is_err());
+            let accounts = vec![greeting_account_info(data.clone())];
+            assert_eq!(Processor::process(&program_id, &accounts, &[64]), Ok(*counter));
+            assert_eq!(take_return_data(), Some(len.to_le_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_add_many() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(10));
+        let accounts = vec![account];
+
+        let add_many = HelloInstruction::AddMany(vec![1, 20, 300]).pack();
+        assert_eq!(add_many, [66, 3, 1, 0, 0, 0, 20, 0, 0, 0, 44, 1, 0, 0]);
+        assert_eq!(Processor::process(&program_id, &accounts, &add_many), Ok(331));
+
+        //the deltas only overflow together, nothing is applied
+        let overflowing = HelloInstruction::AddMany(vec![Counter::MAX - 400, 100]).pack();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &overflowing),
+            Err(GreetingError::Overflow.into())
+        );
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().counter,
+            331
+        );
+
+        //the count must match the deltas that follow it
+        assert_eq!(
+            HelloInstruction::unpack(&[66, 2, 1, 0, 0, 0]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(HelloInstruction::unpack(&[66, 0, 0]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(HelloInstruction::unpack(&[66]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    #[cfg(feature = "no-logs")]
+    fn test_no_logs_is_silent() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(0));
+        let accounts = vec![account];
+
+        take_logs();
+        //the counter and return data behave as usual, only the log lines are gone
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        assert_eq!(take_return_data(), Some(counter_pair(0, DEFAULT_STEP)));
+        process_instruction(&program_id, &accounts, &[2, 1, 0]).unwrap_err();
+        assert_eq!(take_logs(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_assert_equals() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(4));
+        let accounts = vec![account];
+
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        let passing = HelloInstruction::AssertEquals(4 + DEFAULT_STEP).pack();
+        assert_eq!(process_instruction(&program_id, &accounts, &passing), Ok(()));
+
+        let failing = HelloInstruction::AssertEquals(4).pack();
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &failing),
+            Err(GreetingError::AssertionFailed.into())
+        );
+        //asserting never writes, not even the op count
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().op_count,
+            1
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_snapshot_rejects_duplicate_account() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(42));
+        //the runtime hands a repeated account to the program as clones sharing one cell
+        let accounts = vec![account.clone(), account];
+
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &[49]),
+            Err(GreetingError::DuplicateAccount.into())
+        );
+        assert_eq!(*accounts[0].data.borrow(), account_data(42));
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_decrement_and_maybe_close() {
+        let program_id = Pubkey::default();
+        let greeted_key = Pubkey::new_unique();
+        let mut greeted_lamports = 500;
+        let mut greeted_data = account_data(2 * DEFAULT_STEP);
+        let destination_key = Pubkey::new_unique();
+        let mut destination_lamports = 10;
+        let mut destination_data = vec![];
+        let owner = Pubkey::default();
+        let greeted = AccountInfo::new(
+            &greeted_key,
+            false,
+            true,
+            &mut greeted_lamports,
+            &mut greeted_data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        let destination = AccountInfo::new(
+            &destination_key,
+            false,
+            true,
+            &mut destination_lamports,
+            &mut destination_data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        let mut accounts = vec![greeted, destination];
+
+        //anyone could otherwise drain the account into a destination of their choosing
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[71]),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(*accounts[0].data.borrow(), account_data(2 * DEFAULT_STEP));
+        accounts[0].is_signer = true;
+
+        //above one step it is a plain decrement
+        assert_eq!(Processor::process(&program_id, &accounts, &[71]), Ok(DEFAULT_STEP));
+        assert_eq!(accounts[0].lamports(), 500);
+        assert_eq!(accounts[1].lamports(), 10);
+
+        //reaching zero closes the account into the destination
+        assert_eq!(Processor::process(&program_id, &accounts, &[71]), Ok(0));
+        assert_eq!(accounts[0].lamports(), 0);
+        assert_eq!(accounts[1].lamports(), 510);
+        assert!(accounts[0].data.borrow().iter().all(|byte| *byte == 0));
+
+        //a zeroed account can't go any lower
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[71]),
+            Err(GreetingError::Underflow.into())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_set_from_oracle() {
+        let program_id = Pubkey::default();
+        let greeted_key = Pubkey::new_unique();
+        let mut greeted_lamports = 0;
+        let mut greeted_data = account_data(0);
+        let oracle_key = Pubkey::new_unique();
+        let oracle_owner = Pubkey::new_unique();
+        let mut oracle_lamports = 0;
+        //some foreign layout, the value lives after a 3 byte header
+        let mut oracle_data = vec![9, 9, 9];
+        oracle_data.extend_from_slice(&Counter::to_le_bytes(77));
+        oracle_data.push(9);
+        let owner = Pubkey::default();
+        let greeted = AccountInfo::new(
+            &greeted_key,
+            false,
+            true,
+            &mut greeted_lamports,
+            &mut greeted_data,
+            &owner,
+            false,
+            Epoch::default(),
+        );
+        let oracle = AccountInfo::new(
+            &oracle_key,
+            false,
+            false,
+            &mut oracle_lamports,
+            &mut oracle_data,
+            &oracle_owner,
+            false,
+            Epoch::default(),
+        );
+        let accounts = vec![greeted, oracle];
+        let set_from = |offset, owner| HelloInstruction::SetFromOracle { offset, owner }.pack();
+
+        assert_eq!(Processor::process(&program_id, &accounts, &set_from(3, oracle_owner)), Ok(77));
+
+        //an oracle owned by anyone else is refused
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &set_from(3, Pubkey::new_unique())),
+            Err(ProgramError::IllegalOwner)
+        );
+        //as is an offset whose value would run past the end of the data
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &set_from(5, oracle_owner)),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &set_from(u32::MAX, oracle_owner)),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-logs"))]
+    fn test_undecodable_instruction_logs_leading_bytes() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let accounts = vec![];
+
+        take_logs();
+        process_instruction(&program_id, &accounts, &[200, 1, 2]).unwrap_err();
+        assert_eq!(
+            take_logs(),
+            vec!["Could not decode 3 byte(s) of instruction data: [200, 1, 2]".to_string()]
+        );
+
+        //long buffers are cut short
+        process_instruction(&program_id, &accounts, &[2; 40]).unwrap_err();
+        assert!(take_logs().contains(
+            &"Could not decode 40 byte(s) of instruction data: [2, 2, 2, 2, 2, 2, 2, 2]..."
+                .to_string()
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_set_checked() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(1));
+        let accounts = vec![account];
+        let len = GreetingAccount::LEN as u32;
+
+        let matching = HelloInstruction::SetChecked { expected_len: len, value: 30 }.pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &matching), Ok(30));
+
+        let mismatching = HelloInstruction::SetChecked { expected_len: len + 1, value: 40 }.pack();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &mismatching),
+            Err(GreetingError::DataLenMismatch.into())
+        );
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().counter,
+            30
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_add_many_shortvec_count() {
+        //127 is the largest count that fits in one byte, 128 needs two
+        for (count, prefix) in [(127, vec![0x7f]), (128, vec![0x80, 0x01])].iter() {
+            let add_many = HelloInstruction::AddMany(vec![1; *count]);
+            let packed = add_many.pack();
+            assert_eq!(packed[1..1 + prefix.len()], prefix[..]);
+            assert_eq!(packed.len(), 1 + prefix.len() + count * std::mem::size_of::<Counter>());
+            assert_eq!(HelloInstruction::unpack(&packed), Ok(add_many));
+        }
+
+        assert_eq!(instruction::take_short_vec_len(&[0x00, 9]), Ok((0, &[9][..])));
+        assert_eq!(instruction::take_short_vec_len(&[0xff, 0xff, 0x03]), Ok((0xffff, &[][..])));
+        //a padded encoding, one past u16::MAX and a truncated one are all rejected
+        let invalid = [
+            vec![0x80, 0x00],
+            vec![0x80, 0x80, 0x04],
+            vec![0x80],
+            vec![0x80, 0x80, 0x80, 0x01],
+        ];
+        for bytes in invalid.iter() {
+            assert_eq!(
+                instruction::take_short_vec_len(bytes),
+                Err(ProgramError::InvalidInstructionData)
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_only_instructions_check_owner() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = account_data(3);
+        //a well formed state, but written by some other program
+        let foreign_owner = Pubkey::new_unique();
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &foreign_owner,
+            false,
+            Epoch::default(),
+        );
+        let accounts = vec![account.clone(), account];
+
+        let reads = [
+            HelloInstruction::VerifyChecksum(0),
+            HelloInstruction::GetDataLen,
+            HelloInstruction::AssertEquals(3),
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::Snapshot,
+            #[cfg(feature = "debug-export")]
+            HelloInstruction::ExportHex,
+        ];
+        for read in reads.iter() {
+            assert_eq!(
+                Processor::process(&program_id, &accounts, &read.pack()),
+                Err(ProgramError::IncorrectProgramId),
+                "{:?} read a foreign account",
+                read
+            );
+        }
+    }
+
+    #[test]
+    fn test_unpack_with_tag_base() {
+        let base = 100;
+        let set = HelloInstruction::Set(7);
+        let packed = set.pack_with_base(base);
+        assert_eq!(packed[0], base + 2);
+        assert_eq!(HelloInstruction::unpack_with_base(&packed, base), Ok(set));
+        assert_eq!(HelloInstruction::unpack_with_base(&[base], base), Ok(HelloInstruction::Increment));
+
+        //the unshifted tags now belong to the host, and the envelope moves along
+        assert_eq!(
+            HelloInstruction::unpack_with_base(&[0], base),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        let envelope = [instruction::VERSIONED_ENVELOPE_TAG.wrapping_add(base), 1, base + 1];
+        assert_eq!(
+            HelloInstruction::unpack_with_base(&envelope, base),
+            Ok(HelloInstruction::Decrement)
+        );
+
+        //the default base leaves tags as listed
+        assert_eq!(instruction::TAG_BASE, 0);
+        assert_eq!(HelloInstruction::Set(7).pack(), HelloInstruction::Set(7).pack_with_base(0));
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_increment_with_memo() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(0));
+        let accounts = vec![account];
+
+        take_logs();
+        let with_memo = HelloInstruction::IncrementWithMemo("weekly sync".to_string()).pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &with_memo), Ok(DEFAULT_STEP));
+        #[cfg(not(feature = "no-logs"))]
+        assert!(take_logs().contains(&format!("{}:MEMO weekly sync", LOG_PREFIX)));
+
+        let mut invalid_utf8 = vec![80, b'o', b'k'];
+        invalid_utf8.push(0xff);
+        let mut oversized = vec![80];
+        oversized.resize(2 + instruction::MAX_MEMO_LEN, b'a');
+        for rejected in [invalid_utf8, oversized].iter() {
+            assert_eq!(
+                Processor::process(&program_id, &accounts, rejected),
+                Err(ProgramError::InvalidInstructionData)
+            );
+        }
+        //a memo of exactly the limit is fine
+        let mut longest = vec![80];
+        longest.resize(1 + instruction::MAX_MEMO_LEN, b'a');
+        assert_eq!(Processor::process(&program_id, &accounts, &longest), Ok(2 * DEFAULT_STEP));
+    }
+
+    #[test]
+    fn test_who_am_i() {
+        install_test_stubs();
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = account_data(0);
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            Epoch::default(),
+        );
+        let accounts = vec![account];
+
+        process_instruction(&program_id, &accounts, &[83]).unwrap();
+        assert_eq!(take_return_data(), Some(program_id.to_bytes().to_vec()));
+    }
+
+    #[test]
+    #[cfg(feature = "verify-writes")]
+    fn test_verified_write_passes_on_sized_account() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(vec![0; GreetingAccount::LEN]);
+        let accounts = vec![account];
+
+        assert_eq!(Processor::process(&program_id, &accounts, &[0]), Ok(DEFAULT_STEP));
+        let set = HelloInstruction::Set(9).pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &set), Ok(9));
+    }
+
+    #[test]
+    #[cfg(feature = "governance")]
+    fn test_commit_reveal() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(1));
+        let accounts = vec![account];
+        let salt = [42; 32];
+        let reveal = |value| HelloInstruction::Reveal { value, salt }.pack();
+
+        //nothing committed yet
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &reveal(50)),
+            Err(GreetingError::CommitmentMismatch.into())
+        );
+
+        let hash = commitment_hash(50, &salt);
+        let commit = HelloInstruction::Commit(hash).pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &commit), Ok(1));
+        //all of the hash is kept, not just a prefix of it
+        let state = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(state.commitment, hash);
+        //a different value, or the right one with another salt, doesn't open it
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &reveal(51)),
+            Err(GreetingError::CommitmentMismatch.into())
+        );
+        let wrong_salt = HelloInstruction::Reveal { value: 50, salt: [0; 32] }.pack();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &wrong_salt),
+            Err(GreetingError::CommitmentMismatch.into())
+        );
+
+        assert_eq!(Processor::process(&program_id, &accounts, &reveal(50)), Ok(50));
+        //and it can't be replayed
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &reveal(50)),
+            Err(GreetingError::CommitmentMismatch.into())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_get_many() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let owner = Pubkey::default();
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut lamports = [0; 3];
+        let mut data = [account_data(7), account_data(300), account_data(0)];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, false, lamports, data, &owner, false, Epoch::default())
+            })
+            .collect();
+
+        assert_eq!(Processor::process(&program_id, &accounts, &[87]), Ok(7));
+        let counters: Vec<Counter> = take_return_data()
+            .unwrap()
+            .chunks(std::mem::size_of::<Counter>())
+            .map(|counter| Counter::try_from_slice(counter).unwrap())
+            .collect();
+        assert_eq!(counters, vec![7, 300, 0]);
+
+        let too_many = vec![accounts[0].clone(); instruction::MAX_GET_MANY + 1];
+        assert_eq!(
+            Processor::process(&program_id, &too_many, &[87]),
+            Err(GreetingError::TooManyAccounts.into())
+        );
+    }
+
+    #[test]
+    fn test_disabled_groups_reject_their_tags() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(3));
+        let accounts = vec![account.clone(), account.clone(), account];
+
+        let groups: [(bool, &[u8]); 3] = [
+            (cfg!(feature = "arithmetic-ext"), &[59, 60, 66, 75, 80, 92, 97]),
+            (cfg!(feature = "governance"), &[57, 85, 86, 93, 96, 98]),
+            (cfg!(feature = "multi-account"), &[49, 52, 71, 72, 87, 90]),
+        ];
+        for (_, tags) in groups.iter().filter(|(enabled, _)| !enabled) {
+            for tag in tags.iter() {
+                assert_eq!(
+                    Processor::process(&program_id, &accounts, &[*tag]),
+                    Err(ProgramError::InvalidInstructionData),
+                    "disabled tag {} was processed",
+                    tag
+                );
+            }
+        }
+        //nothing was written through
+        assert_eq!(GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().counter, 3);
+    }
+
+    #[test]
+    #[cfg(feature = "multi-account")]
+    fn test_set_to_checksum() {
+        let program_id = Pubkey::default();
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let owners = [program_id, Pubkey::new_unique()];
+        let mut lamports = [0; 2];
+        //two whole words and a trailing byte, which is zero padded
+        let mut data = [account_data(0), vec![1, 0, 0, 0, 2, 1, 0, 0, 3]];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(owners.iter())
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|(((key, owner), lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, owner, false, Epoch::default())
+            })
+            .collect();
+
+        //the source's owner doesn't matter, its bytes are all that is read
+        assert_eq!(Processor::process(&program_id, &accounts, &[90]), Ok(1 + 0x102 + 3));
+        assert_eq!(accounts[1].data.borrow()[..], [1, 0, 0, 0, 2, 1, 0, 0, 3]);
+
+        //the stored value is the state checksum the program itself computes
+        let source = accounts[1].data.borrow().to_vec();
+        let stored = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().counter;
+        assert_eq!(i128::from(stored), i128::from(checksum(&source)));
+
+        //the largest fingerprint only fits a counter that holds every u32
+        accounts[1].data.borrow_mut().copy_from_slice(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0]);
+        #[cfg(not(feature = "signed-counter"))]
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[90]),
+            Ok(Counter::from(u32::MAX))
+        );
+        #[cfg(feature = "signed-counter")]
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[90]),
+            Err(GreetingError::Overflow.into())
+        );
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts[..1], &[90]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_add_many_rejects_oversized_batch() {
+        install_test_stubs();
+        let full = HelloInstruction::AddMany(vec![1; instruction::MAX_BATCH]);
+        assert_eq!(HelloInstruction::unpack(&full.pack()), Ok(full));
+
+        let oversized = HelloInstruction::AddMany(vec![1; instruction::MAX_BATCH + 1]).pack();
+        take_logs();
+        assert_eq!(HelloInstruction::unpack(&oversized), Err(ProgramError::InvalidInstructionData));
+        #[cfg(not(feature = "no-logs"))]
+        assert_eq!(
+            take_logs(),
+            vec![format!(
+                "AddMany carries {} deltas, at most {} are allowed",
+                instruction::MAX_BATCH + 1,
+                instruction::MAX_BATCH
+            )]
+        );
+
+        //only the count is looked at, the deltas needn't even be there
+        let mut header = vec![66];
+        header.extend_from_slice(&oversized[1..3]);
+        assert_eq!(HelloInstruction::unpack(&header), Err(ProgramError::InvalidInstructionData));
+        #[cfg(not(feature = "no-logs"))]
+        assert!(take_logs()[0].starts_with("AddMany carries"));
+
+        //a count past u16::MAX still packs, into a shortvec the decoder won't accept
+        let huge = HelloInstruction::AddMany(vec![1; usize::from(u16::MAX) + 1]).pack();
+        assert_eq!(huge[1..4], [0x80, 0x80, 0x04]);
+        assert_eq!(HelloInstruction::unpack(&huge), Err(ProgramError::InvalidInstructionData));
+
+        #[cfg(feature = "client")]
+        {
+            let (program_id, greeted) = (Pubkey::default(), Pubkey::default());
+            let full = vec![1; instruction::MAX_BATCH];
+            assert!(client::add_many(&program_id, &greeted, full).is_ok());
+            assert_eq!(
+                client::add_many(&program_id, &greeted, vec![1; instruction::MAX_BATCH + 1]),
+                Err(ProgramError::InvalidInstructionData)
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_mul_sample() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(1));
+        let accounts = vec![account];
+
+        let mut product = 1;
+        for sample in [2, 3, 7, 100].iter() {
+            product *= *sample as Counter;
+            let mul = HelloInstruction::MulSample(*sample).pack();
+            assert_eq!(Processor::process(&program_id, &accounts, &mul), Ok(product));
+        }
+        assert_eq!(product, 4200);
+
+        //4200 * 2^20 is past i32 and u32 alike, the product is left as it was
+        let overflowing = HelloInstruction::MulSample(1 << 20).pack();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &overflowing),
+            Err(GreetingError::Overflow.into())
+        );
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().counter,
+            4200
+        );
+
+        //the widest sample can't overflow the intermediate product either
+        let widest = HelloInstruction::MulSample(u32::MAX).pack();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &widest),
+            Err(GreetingError::Overflow.into())
+        );
+        let zero = HelloInstruction::MulSample(0).pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &zero), Ok(0));
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_mul_sample_on_fresh_account() {
+        let program_id = Pubkey::default();
+        let accounts = vec![greeting_account_info(vec![0; GreetingAccount::LEN])];
+        let mul = |sample| HelloInstruction::MulSample(sample).pack();
+
+        //the product starts from 1, a zeroed counter would keep it at zero for good
+        assert_eq!(Processor::process(&program_id, &accounts, &mul(3)), Ok(3));
+        assert_eq!(Processor::process(&program_id, &accounts, &mul(4)), Ok(12));
+        let state = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert!(state.product_started);
+
+        //once started, the counter is multiplied as it stands
+        let set = HelloInstruction::Set(5).pack();
+        Processor::process(&program_id, &accounts, &set).unwrap();
+        assert_eq!(Processor::process(&program_id, &accounts, &mul(2)), Ok(10));
+    }
+
+    #[test]
+    fn test_tag_try_from() {
+        use crate::instruction::Tag;
+        use std::convert::TryFrom;
+
+        for tag in [0, 1, 2, 61, 64, 70, 83].iter() {
+            assert_eq!(Tag::try_from(*tag).map(u8::from), Ok(*tag));
+        }
+        assert_eq!(
+            Tag::try_from(2).unwrap().decode(&Counter::to_le_bytes(7)),
+            Ok(HelloInstruction::Set(7))
+        );
+        //a known tag with a bad payload is for the decoder to reject
+        assert_eq!(
+            Tag::try_from(2).unwrap().decode(&[7]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+
+        //the envelope tag is handled by unpack, no decoder is registered for it
+        for tag in [3, 48, 200, instruction::VERSIONED_ENVELOPE_TAG, u8::MAX].iter() {
+            assert_eq!(Tag::try_from(*tag), Err(ProgramError::InvalidInstructionData));
+        }
+        //every byte unpack accepts as a tag is one Tag accepts
+        for tag in 0..=u8::MAX {
+            let unpacks = HelloInstruction::unpack(&[tag]).is_ok();
+            assert!(!unpacks || Tag::try_from(tag).is_ok(), "tag {}", tag);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "governance")]
+    fn test_set_in_window() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(1));
+        let accounts = vec![account];
+        let set_in_window =
+            |value| HelloInstruction::SetInWindow { value, start: 100, end: 200 }.pack();
+
+        //without a clock there is nothing to check the window against
+        CLOCK.with(|clock| *clock.borrow_mut() = None);
+        take_logs();
+        #[cfg(feature = "strict-clock")]
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &set_in_window(5)),
+            Err(ProgramError::UnsupportedSysvar)
+        );
+        //the lenient default succeeds without setting anything, and says so
+        #[cfg(not(feature = "strict-clock"))]
+        {
+            let before = accounts[0].data.borrow().to_vec();
+            assert_eq!(Processor::process(&program_id, &accounts, &set_in_window(5)), Ok(1));
+            assert_eq!(accounts[0].data.borrow()[..], before[..]);
+            #[cfg(not(feature = "no-logs"))]
+            assert!(take_logs().iter().any(|line| line.starts_with("Warning: the clock")));
+        }
+
+        //both ends of the window are inside it
+        for (now, value) in [(100, 5), (150, 6), (200, 7)].iter() {
+            set_unix_timestamp(*now);
+            assert_eq!(
+                Processor::process(&program_id, &accounts, &set_in_window(*value)),
+                Ok(*value)
+            );
+        }
+
+        for now in [99, 201, i64::MIN, i64::MAX].iter() {
+            set_unix_timestamp(*now);
+            assert_eq!(
+                Processor::process(&program_id, &accounts, &set_in_window(8)),
+                Err(GreetingError::OutsideWindow.into())
+            );
+        }
+        assert_eq!(
+            GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().counter,
+            7
+        );
+    }
+
+    #[test]
+    fn test_writes_seal_stored_checksum() {
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(5));
+        let accounts = vec![account];
+
+        process_instruction(&program_id, &accounts, &[0]).unwrap();
+        let state = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(state.counter, 5 + DEFAULT_STEP);
+        assert_ne!(state.checksum, 0);
+        assert_eq!(state.checksum, state.fields_checksum().unwrap());
+
+        //edit the counter behind the program's back
+        accounts[0].data.borrow_mut()[..std::mem::size_of::<Counter>()]
+            .copy_from_slice(&Counter::to_le_bytes(100));
+        let edited = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(edited.checksum, state.checksum);
+        assert_ne!(edited.checksum, edited.fields_checksum().unwrap());
+
+        #[cfg(feature = "verify-reads")]
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &HelloInstruction::AssertEquals(100).pack()),
+            Err(GreetingError::StoredChecksumMismatch.into())
+        );
+        #[cfg(not(feature = "verify-reads"))]
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &HelloInstruction::AssertEquals(100).pack()),
+            Ok(100)
+        );
+    }
+
+    #[test]
+    fn test_apply_at_boundaries() {
+        install_test_stubs();
+        let max = Counter::MAX;
+        //the zero result flag only matters to scaling, covered by test_zero_result_guard
+        let allow = true;
+
+        let increment = HelloInstruction::Increment;
+        assert_eq!(increment.apply(max - DEFAULT_STEP, allow), Ok(max));
+        assert_eq!(increment.apply(max, allow), Err(GreetingError::Overflow));
+        assert_eq!(HelloInstruction::Decrement.apply(max, allow), Ok(max - DEFAULT_STEP));
+        assert_eq!(
+            HelloInstruction::Decrement.apply(Counter::MIN, allow),
+            Err(GreetingError::Underflow)
+        );
+        assert_eq!(HelloInstruction::Set(max).apply(0, allow), Ok(max));
+        assert_eq!(HelloInstruction::Set(0).apply(max, allow), Ok(0));
+
+        #[cfg(feature = "arithmetic-ext")]
+        {
+            let adjust = HelloInstruction::AdjustPercent;
+            assert_eq!(adjust(100).apply(max / 2, allow), Ok(max / 2 * 2));
+            assert_eq!(adjust(100).apply(max, allow), Err(GreetingError::Overflow));
+            assert_eq!(adjust(-100).apply(max, allow), Ok(0));
+
+            let increment_if_equal = HelloInstruction::IncrementIfEqual;
+            let below = max - DEFAULT_STEP;
+            assert_eq!(increment_if_equal(below).apply(below, allow), Ok(max));
+            let overflow = Err(GreetingError::Overflow);
+            assert_eq!(increment_if_equal(max).apply(max, allow), overflow);
+            let mismatch = Err(GreetingError::CasMismatch);
+            assert_eq!(increment_if_equal(1).apply(2, allow), mismatch);
+
+            assert_eq!(HelloInstruction::AddMany(vec![max, 0]).apply(0, allow), Ok(max));
+            assert_eq!(HelloInstruction::AddMany(vec![]).apply(max, allow), Ok(max));
+            let add_many = HelloInstruction::AddMany(vec![max]);
+            assert_eq!(add_many.apply(1, allow), Err(GreetingError::Overflow));
+
+            let memo = HelloInstruction::IncrementWithMemo(String::new());
+            assert_eq!(memo.apply(max - DEFAULT_STEP, allow), Ok(max));
+            assert_eq!(memo.apply(max, allow), Err(GreetingError::Overflow));
+
+            assert_eq!(HelloInstruction::MulSample(1).apply(max, allow), Ok(max));
+            assert_eq!(HelloInstruction::MulSample(0).apply(max, allow), Ok(0));
+            let mul_sample = HelloInstruction::MulSample(2);
+            assert_eq!(mul_sample.apply(max, allow), Err(GreetingError::Overflow));
+        }
+
+        #[cfg(feature = "multi-account")]
+        {
+            let close = HelloInstruction::DecrementAndMaybeClose;
+            assert_eq!(close.apply(DEFAULT_STEP, allow), Ok(0));
+            assert_eq!(close.apply(0, allow), Err(GreetingError::Underflow));
+        }
+
+        //reads, and writes that depend on more than the counter, leave it to the processor
+        assert_eq!(HelloInstruction::GetDataLen.apply(max, allow), Ok(max));
+        assert_eq!(HelloInstruction::AssertEquals(0).apply(max, allow), Ok(max));
+        #[cfg(feature = "arithmetic-ext")]
+        {
+            let set_checked = HelloInstruction::SetChecked { expected_len: 0, value: 1 };
+            assert_eq!(set_checked.apply(max, allow), Ok(max));
+        }
+        #[cfg(feature = "governance")]
+        assert_eq!(HelloInstruction::Reveal { value: 1, salt: [0; 32] }.apply(max, allow), Ok(max));
+        #[cfg(feature = "multi-account")]
+        assert_eq!(HelloInstruction::MergeAverage.apply(max, allow), Ok(max));
+    }
+
+    #[test]
+    fn test_greeting_account_builder() {
+        let writer = Pubkey::new_unique();
+        let built = GreetingAccount::builder()
+            .counter(5)
+            .monotonic(true)
+            .last_writer(writer)
+            .op_count(9)
+            .commitment([3; COMMITMENT_LEN])
+            .return_verbosity(2)
+            .allow_zero_result(false)
+            .product_started(true)
+            .build();
+        let mut expected = GreetingAccount {
+            counter: 5,
+            monotonic: true,
+            last_writer: writer,
+            op_count: 9,
+            commitment: [3; COMMITMENT_LEN],
+            return_verbosity: 2,
+            allow_zero_result: false,
+            product_started: true,
+            checksum: 0,
+            ..GreetingAccount::default()
+        };
+        expected.checksum = expected.fields_checksum().unwrap();
+        assert_eq!(built, expected);
+
+        //unset fields keep their defaults, zero apart from allowing zero results
+        let mut unsealed = GreetingAccount::builder().build();
+        unsealed.checksum = 0;
+        assert_eq!(unsealed, GreetingAccount::default());
+        let counter_only = GreetingAccount::builder().counter(7).build();
+        assert!(!counter_only.monotonic);
+        assert!(counter_only.allow_zero_result);
+        assert_eq!(counter_only.op_count, 0);
+        assert_eq!(counter_only.checksum, counter_only.fields_checksum().unwrap());
+    }
+
+    #[test]
+    fn test_increment_is_one_byte() {
+        assert_eq!(HelloInstruction::Increment.pack(), vec![0]);
+        assert_eq!(HelloInstruction::unpack(&[0]), Ok(HelloInstruction::Increment));
+        //nothing shorter decodes, a buffer needs at least its tag
+        assert_eq!(HelloInstruction::unpack(&[]), Err(ProgramError::InvalidInstructionData));
+        //the envelope carries the same instruction in three bytes, pack never uses it
+        assert_eq!(
+            HelloInstruction::unpack(&[instruction::VERSIONED_ENVELOPE_TAG, 1, 0]),
+            Ok(HelloInstruction::Increment)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "governance")]
+    fn test_return_verbosity() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let accounts = vec![greeting_account_info(account_data(10))];
+        let width = std::mem::size_of::<Counter>();
+        let verbosity = |level| HelloInstruction::SetVerbosity(level).pack();
+        let set = |value| HelloInstruction::Set(value).pack();
+        let transition = |previous: Counter, current: Counter| {
+            [previous.to_le_bytes(), current.to_le_bytes()].concat()
+        };
+
+        //a new account gets the transition
+        process_instruction(&program_id, &accounts, &set(11)).unwrap();
+        assert_eq!(take_return_data(), Some(transition(10, 11)));
+
+        process_instruction(&program_id, &accounts, &verbosity(VERBOSITY_COUNTER)).unwrap();
+        assert_eq!(take_return_data().unwrap().len(), width);
+        process_instruction(&program_id, &accounts, &set(12)).unwrap();
+        assert_eq!(take_return_data(), Some(Counter::to_le_bytes(12).to_vec()));
+
+        process_instruction(&program_id, &accounts, &verbosity(VERBOSITY_FULL)).unwrap();
+        process_instruction(&program_id, &accounts, &set(13)).unwrap();
+        let full = take_return_data().unwrap();
+        assert_eq!(full.len(), 2 * width + 8 + 32);
+        let state = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(full[..2 * width], transition(12, 13)[..]);
+        assert_eq!(full[2 * width..2 * width + 8], state.op_count.to_le_bytes());
+        assert_eq!(full[2 * width + 8..], state.last_writer.to_bytes());
+
+        process_instruction(&program_id, &accounts, &verbosity(VERBOSITY_TRANSITION)).unwrap();
+        process_instruction(&program_id, &accounts, &set(14)).unwrap();
+        assert_eq!(take_return_data(), Some(transition(13, 14)));
+
+        //only the known levels decode
+        assert_eq!(HelloInstruction::unpack(&[96, 3]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(HelloInstruction::unpack(&[96]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    #[cfg(all(feature = "arithmetic-ext", feature = "governance"))]
+    fn test_zero_result_guard() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        //zero filled apart from the counter, the way an account the program never wrote is
+        let data = GreetingAccount::builder()
+            .counter(10)
+            .allow_zero_result(false)
+            .build()
+            .try_to_vec()
+            .unwrap();
+        let accounts = vec![greeting_account_info(data)];
+        let allow = |allow| HelloInstruction::SetAllowZeroResult(allow).pack();
+        let set = |value| HelloInstruction::Set(value).pack();
+        let zero = HelloInstruction::MulSample(0).pack();
+        let wipe = HelloInstruction::AdjustPercent(-100).pack();
+
+        //a new account allows it
+        assert_eq!(Processor::process(&program_id, &accounts, &zero), Ok(0));
+        let state = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert!(state.allow_zero_result);
+
+        process_instruction(&program_id, &accounts, &set(10)).unwrap();
+        assert_eq!(Processor::process(&program_id, &accounts, &allow(false)), Ok(10));
+        let state = GreetingAccount::try_from_slice(&accounts[0].data.borrow()).unwrap();
+        assert!(!state.allow_zero_result);
+        let before = accounts[0].data.borrow().to_vec();
+        for instruction in [&zero, &wipe].iter() {
+            assert_eq!(
+                Processor::process(&program_id, &accounts, instruction),
+                Err(GreetingError::ZeroResultForbidden.into())
+            );
+            assert_eq!(accounts[0].data.borrow()[..], before[..]);
+        }
+        //scaling that leaves something behind is still fine, as is setting zero outright
+        let halve = HelloInstruction::AdjustPercent(-50).pack();
+        assert_eq!(Processor::process(&program_id, &accounts, &halve), Ok(5));
+        assert_eq!(Processor::process(&program_id, &accounts, &set(0)), Ok(0));
+        //and a counter that is already zero has nothing to lose
+        assert_eq!(Processor::process(&program_id, &accounts, &zero), Ok(0));
+
+        process_instruction(&program_id, &accounts, &set(10)).unwrap();
+        assert_eq!(Processor::process(&program_id, &accounts, &allow(true)), Ok(10));
+        assert_eq!(Processor::process(&program_id, &accounts, &wipe), Ok(0));
+
+        let forbidden = Err(GreetingError::ZeroResultForbidden);
+        assert_eq!(HelloInstruction::MulSample(0).apply(3, false), forbidden);
+        assert_eq!(HelloInstruction::MulSample(0).apply(3, true), Ok(0));
+        assert_eq!(HelloInstruction::MulSample(2).apply(3, false), Ok(6));
+
+        //the flag is a single 0 or 1 byte
+        assert_eq!(HelloInstruction::unpack(&[98, 2]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(HelloInstruction::unpack(&[98]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    #[cfg(feature = "arithmetic-ext")]
+    fn test_add_then_assert() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(10));
+        let accounts = vec![account];
+        let add_then_assert =
+            |delta, expected| HelloInstruction::AddThenAssert { delta, expected }.pack();
+
+        assert_eq!(Processor::process(&program_id, &accounts, &add_then_assert(5, 15)), Ok(15));
+
+        //a post-condition that doesn't hold leaves the account exactly as it was
+        let before = accounts[0].data.borrow().to_vec();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &add_then_assert(5, 21)),
+            Err(GreetingError::AssertionFailed.into())
+        );
+        assert_eq!(accounts[0].data.borrow()[..], before[..]);
+
+        //an overflowing delta fails before the post-condition is looked at
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &add_then_assert(Counter::MAX, 15)),
+            Err(GreetingError::Overflow.into())
+        );
+        assert_eq!(accounts[0].data.borrow()[..], before[..]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "arithmetic-ext", feature = "signed-counter"))]
+    fn test_add_then_assert_negative_delta() {
+        install_test_stubs();
+        let program_id = Pubkey::default();
+        let account = greeting_account_info(account_data(-5));
+        let accounts = vec![account];
+        let add_then_assert =
+            |delta, expected| HelloInstruction::AddThenAssert { delta, expected }.pack();
+
+        assert_eq!(Processor::process(&program_id, &accounts, &add_then_assert(-3, -8)), Ok(-8));
+
+        //running off the bottom is an underflow, and reports no headroom
+        let before = accounts[0].data.borrow().to_vec();
+        take_return_data();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &add_then_assert(Counter::MIN, 0)),
+            Err(GreetingError::Underflow.into())
+        );
+        assert_eq!(take_return_data(), None);
+        assert_eq!(accounts[0].data.borrow()[..], before[..]);
+        assert_eq!(
+            HelloInstruction::AddThenAssert { delta: Counter::MIN, expected: 0 }.apply(-5, true),
+            Err(GreetingError::Underflow)
+        );
+    }
+
+    #[test]
+    fn test_counter_width_matches_set_payload() {
+        let width = std::mem::size_of::<Counter>();
+        assert_eq!(HelloInstruction::Set(1).pack().len(), 1 + width);
+
+        let mut set = vec![2];
+        set.extend_from_slice(&Counter::MAX.to_le_bytes());
+        assert_eq!(set.len(), 1 + width);
+        assert_eq!(HelloInstruction::unpack(&set), Ok(HelloInstruction::Set(Counter::MAX)));
+        //a byte more or less than a counter is rejected
+        assert_eq!(
+            HelloInstruction::unpack(&set[..width]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        set.push(0);
+        assert_eq!(HelloInstruction::unpack(&set), Err(ProgramError::InvalidInstructionData));
+
+        //the counter leads the serialized state
+        let state = account_data(Counter::MAX);
+        assert_eq!(state[..width], Counter::MAX.to_le_bytes());
     }
 }
@@ -0,0 +1,710 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+#[cfg(feature = "arithmetic-ext")]
+use std::convert::TryFrom;
+#[cfg(feature = "governance")]
+use solana_program::{clock::Clock, sysvar::Sysvar};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::set_return_data,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use crate::error::GreetingError;
+use crate::instruction::HelloInstruction;
+#[cfg(feature = "multi-account")]
+use crate::instruction::MAX_GET_MANY;
+#[cfg(feature = "multi-account")]
+use crate::checksum;
+use crate::{Counter, GreetingAccount, DEFAULT_STEP, LOG_PREFIX};
+use crate::{VERBOSITY_COUNTER, VERBOSITY_FULL};
+#[cfg(feature = "governance")]
+use crate::{commitment_hash, COMMITMENT_LEN};
+
+/// Instruction processing, kept apart from the entrypoint so callers other than the
+/// runtime (tests, composing code) can see the outcome of an instruction
+pub struct Processor;
+
+impl Processor {
+    /// Run a single instruction and return the greeted account's counter afterwards
+    pub fn process(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+    ) -> Result<Counter, ProgramError> {
+        let instructions = match HelloInstruction::unpack(instruction_data) {
+            Ok(instructions) => instructions,
+            Err(error) => {
+                log_undecodable(instruction_data);
+                return Err(error);
+            }
+        };
+
+        log!("Hello World Rust program entrypoint");
+
+        require_accounts(accounts, instructions.accounts_required())?;
+
+        // Iterating accounts is safer than indexing
+        let accounts_iter = &mut accounts.iter();
+
+        // Get the account to say hello to
+        let account = next_account_info(accounts_iter)?;
+
+        // The account must be owned by the program before its data is trusted, this guards
+        // the read only instructions as much as the mutations
+        if account.owner != program_id {
+            log!("Greeted account does not have the correct program id");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        //answered before the state is read, so it still works on accounts of an older or
+        //newer size, which are exactly the ones a client asks about
+        if let HelloInstruction::GetDataLen = instructions {
+            return Ok(get_data_len(account));
+        }
+
+        // Increment and store the number of times the account has been greeted
+        let mut greeting_account = GreetingAccount::try_from_slice(&account.data.borrow())?;
+        #[cfg(feature = "verify-reads")]
+        check_stored_checksum(account, &greeting_account)?;
+        //an account the program has never written is still zero filled, which would read
+        //as forbidding zero results. It starts out allowing them, like the default state
+        if greeting_account.op_count == 0 {
+            greeting_account.allow_zero_result = true;
+        }
+        let previous = greeting_account.counter;
+        //instructions that only do arithmetic on the counter are settled here, the match
+        //below sets the counter for the ones that need more than that
+        let current = starting_counter(&instructions, &mut greeting_account);
+        greeting_account.counter = instructions.apply(current, greeting_account.allow_zero_result)?;
+
+        let event = match instructions{
+            HelloInstruction::Increment => "INCREMENT",
+            HelloInstruction::Decrement => "DECREMENT",
+            HelloInstruction::Set(_) => "SET",
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::Snapshot => {
+                //the snapshot is written to the next account, the greeted account is left as is
+                let destination = next_account_info(accounts_iter)?;
+                require_distinct(account, destination)?;
+                snapshot(program_id, &greeting_account, destination)?;
+                return Ok(greeting_account.counter);
+            }
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::MergeAverage => {
+                //the greeted account receives the average of the next two accounts
+                let a = load_greeting_account(program_id, next_account_info(accounts_iter)?)?;
+                let b = load_greeting_account(program_id, next_account_info(accounts_iter)?)?;
+                //summing in i128 means two large counters can't overflow before dividing,
+                //whether the counter is signed or not, and however wide it is
+                let average = (i128::from(a.counter) + i128::from(b.counter)) / 2;
+                //the average of two counters always lies between them, so it fits back
+                greeting_account.counter = average as Counter;
+                "MERGE_AVERAGE"
+            }
+            #[cfg(feature = "debug-export")]
+            HelloInstruction::ExportHex => {
+                export_hex(&greeting_account)?;
+                return Ok(greeting_account.counter);
+            }
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AdjustPercent(_) => "ADJUST_PERCENT",
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::IncrementIfEqual(_) => "INCREMENT_IF_EQUAL",
+            HelloInstruction::VerifyChecksum(expected) => {
+                let actual = greeting_account.fields_checksum()?;
+                if actual != expected {
+                    log!("State checksum is {} but {} was expected", actual, expected);
+                    return Err(GreetingError::ChecksumMismatch.into());
+                }
+                return Ok(greeting_account.counter);
+            }
+            //already answered before the state was read
+            HelloInstruction::GetDataLen => return Ok(get_data_len(account)),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddMany(_) => "ADD_MANY",
+            HelloInstruction::AssertEquals(expected) => {
+                if greeting_account.counter != expected {
+                    log!("Expected counter {} but found {}", expected, greeting_account.counter);
+                    return Err(GreetingError::AssertionFailed.into());
+                }
+                return Ok(greeting_account.counter);
+            }
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::DecrementAndMaybeClose => {
+                //closing hands the account's lamports to whoever is named as destination
+                if !account.is_signer {
+                    log!("Account {} must sign to be decremented towards closing", account.key);
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+                let destination = next_account_info(accounts_iter)?;
+                require_distinct(account, destination)?;
+                if greeting_account.counter == 0 {
+                    if greeting_account.monotonic {
+                        log!("Counter is monotonic, refusing to lower it from {} to 0", previous);
+                        return Err(GreetingError::NonMonotonic.into());
+                    }
+                    close_account(account, destination)?;
+                    log_event("CLOSE", 0);
+                    set_mutation_return_data(previous, &greeting_account);
+                    return Ok(0);
+                }
+                "DECREMENT_AND_MAYBE_CLOSE"
+            }
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::SetFromOracle { offset, owner } => {
+                let oracle = next_account_info(accounts_iter)?;
+                greeting_account.counter = read_oracle(oracle, offset as usize, &owner)?;
+                "SET_FROM_ORACLE"
+            }
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::MulSample(_) => "MUL_SAMPLE",
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddThenAssert { .. } => "ADD_THEN_ASSERT",
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::SetToChecksum => {
+                let source = next_account_info(accounts_iter)?;
+                let fingerprint = checksum(&source.data.borrow());
+                greeting_account.counter = counter_from_checksum(fingerprint)?;
+                "SET_TO_CHECKSUM"
+            }
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::SetChecked { expected_len, value } => {
+                if account.data_len() != expected_len as usize {
+                    log!(
+                        "Greeted account holds {} bytes but {} were expected",
+                        account.data_len(),
+                        expected_len
+                    );
+                    return Err(GreetingError::DataLenMismatch.into());
+                }
+                greeting_account.counter = value;
+                "SET_CHECKED"
+            }
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::IncrementWithMemo(ref memo) => {
+                log!("{}:MEMO {}", LOG_PREFIX, memo);
+                "INCREMENT_WITH_MEMO"
+            }
+            HelloInstruction::WhoAmI => {
+                set_return_data(program_id.as_ref());
+                return Ok(greeting_account.counter);
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::Commit(hash) => {
+                greeting_account.commitment = hash;
+                "COMMIT"
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::Reveal { value, salt } => {
+                let revealed = commitment_hash(value, &salt);
+                if greeting_account.commitment == [0; COMMITMENT_LEN]
+                    || revealed != greeting_account.commitment
+                {
+                    log!("Revealed value {} doesn't match the stored commitment", value);
+                    return Err(GreetingError::CommitmentMismatch.into());
+                }
+                greeting_account.counter = value;
+                //a commitment can only be revealed once
+                greeting_account.commitment = [0; COMMITMENT_LEN];
+                "REVEAL"
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetInWindow { value, start, end } => {
+                let now = match Clock::get() {
+                    Ok(clock) => clock.unix_timestamp,
+                    //without strict-clock an unreadable clock skips the set instead of
+                    //failing, the window can't be checked so the counter is left alone
+                    #[cfg(not(feature = "strict-clock"))]
+                    Err(error) => {
+                        log!("Warning: the clock can't be read ({}), not setting {}", error, value);
+                        return Ok(greeting_account.counter);
+                    }
+                    #[cfg(feature = "strict-clock")]
+                    Err(error) => return Err(error),
+                };
+                if now < start || now > end {
+                    log!("It is {}, outside the window {} to {}", now, start, end);
+                    return Err(GreetingError::OutsideWindow.into());
+                }
+                greeting_account.counter = value;
+                "SET_IN_WINDOW"
+            }
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::GetMany => {
+                if accounts.len() > MAX_GET_MANY {
+                    log!(
+                        "GetMany reads at most {} accounts, {} were passed",
+                        MAX_GET_MANY,
+                        accounts.len()
+                    );
+                    return Err(GreetingError::TooManyAccounts.into());
+                }
+                let mut counters = greeting_account.counter.to_le_bytes().to_vec();
+                for other in accounts_iter {
+                    let other = load_greeting_account(program_id, other)?;
+                    counters.extend_from_slice(&other.counter.to_le_bytes());
+                }
+                set_return_data(&counters);
+                return Ok(greeting_account.counter);
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetMonotonic => {
+                //there is deliberately no way to turn this back off
+                greeting_account.monotonic = true;
+                "SET_MONOTONIC"
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetVerbosity(level) => {
+                greeting_account.return_verbosity = level;
+                "SET_VERBOSITY"
+            }
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetAllowZeroResult(allow) => {
+                greeting_account.allow_zero_result = allow;
+                "SET_ALLOW_ZERO_RESULT"
+            }
+        };
+
+        greeting_account.last_writer = *last_writer(accounts, account);
+        greeting_account.op_count = greeting_account
+            .op_count
+            .checked_add(1)
+            .ok_or(GreetingError::Overflow)?;
+        //this version of the program doesn't use the reserved space, keep it zeroed
+        greeting_account.clear_reserved();
+        //sealed over the fields exactly as they are about to be stored
+        greeting_account.checksum = greeting_account.fields_checksum()?;
+
+        if greeting_account.monotonic && greeting_account.counter < previous {
+            log!(
+                "Counter is monotonic, refusing to lower it from {} to {}",
+                previous,
+                greeting_account.counter
+            );
+            return Err(GreetingError::NonMonotonic.into());
+        }
+
+        store_greeting_account(account, &greeting_account)?;
+
+        log!("Greeted {} time(s)!", greeting_account.counter);
+        log_event(event, greeting_account.counter);
+        log_change(greeting_account.op_count, greeting_account.counter);
+        set_mutation_return_data(previous, &greeting_account);
+
+        Ok(greeting_account.counter)
+    }
+}
+
+impl HelloInstruction {
+    /// The counter this instruction leaves behind when it finds `current`, with every
+    /// arithmetic check the processor makes. `allow_zero_result` is the account's flag of
+    /// that name, which decides whether scaling may take a non zero counter to zero.
+    /// Instructions that need more than these to decide, such as other accounts, the clock
+    /// or the stored commitment, and the ones that only read the counter, leave `current`
+    /// as it is. The processor sets the counter for the former itself
+    pub fn apply(
+        &self,
+        current: Counter,
+        //only scaling looks at it, and scaling is part of arithmetic-ext
+        #[cfg_attr(not(feature = "arithmetic-ext"), allow(unused_variables))]
+        allow_zero_result: bool,
+    ) -> Result<Counter, GreetingError> {
+        match self {
+            HelloInstruction::Increment => add_or_report_overflow(current, DEFAULT_STEP),
+            HelloInstruction::Decrement => sub_or_report_underflow(current, DEFAULT_STEP),
+            HelloInstruction::Set(value) => Ok(*value),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AdjustPercent(percent) => adjust_by_percent(current, *percent)
+                .and_then(|next| check_zero_result(current, next, allow_zero_result)),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::IncrementIfEqual(expected) => {
+                if current != *expected {
+                    log!("Expected counter {} but found {}", expected, current);
+                    return Err(GreetingError::CasMismatch);
+                }
+                add_or_report_overflow(current, DEFAULT_STEP)
+            }
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddMany(deltas) => add_all(current, deltas),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::IncrementWithMemo(_) => add_or_report_overflow(current, DEFAULT_STEP),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::MulSample(sample) => multiply_by(current, *sample)
+                .and_then(|next| check_zero_result(current, next, allow_zero_result)),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::AddThenAssert { delta, expected } => {
+                add_or_report_overflow(current, *delta).and_then(|sum| {
+                    if sum != *expected {
+                        log!("Adding {} left the counter at {}, not {}", delta, sum, expected);
+                        return Err(GreetingError::AssertionFailed);
+                    }
+                    Ok(sum)
+                })
+            }
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::DecrementAndMaybeClose => sub_stopping_at_zero(current, DEFAULT_STEP),
+            #[cfg(feature = "arithmetic-ext")]
+            HelloInstruction::SetChecked { .. } => Ok(current),
+            HelloInstruction::VerifyChecksum(_)
+            | HelloInstruction::GetDataLen
+            | HelloInstruction::AssertEquals(_)
+            | HelloInstruction::WhoAmI => Ok(current),
+            #[cfg(feature = "debug-export")]
+            HelloInstruction::ExportHex => Ok(current),
+            #[cfg(feature = "governance")]
+            HelloInstruction::SetMonotonic
+            | HelloInstruction::Commit(_)
+            | HelloInstruction::Reveal { .. }
+            | HelloInstruction::SetInWindow { .. }
+            | HelloInstruction::SetVerbosity(_)
+            | HelloInstruction::SetAllowZeroResult(_) => Ok(current),
+            #[cfg(feature = "multi-account")]
+            HelloInstruction::Snapshot
+            | HelloInstruction::MergeAverage
+            | HelloInstruction::SetFromOracle { .. }
+            | HelloInstruction::GetMany
+            | HelloInstruction::SetToChecksum => Ok(current),
+        }
+    }
+}
+
+/// How much of an undecodable buffer to log, enough to see the tag and the start of the
+/// payload without paying to log a whole transaction's worth of bytes
+const LOGGED_INSTRUCTION_BYTES: usize = 8;
+
+/// Log the start of instruction data that failed to decode, e.g.
+/// `Could not decode 5 byte(s) of instruction data: [200, 0, 0, 0, 0]`
+fn log_undecodable(instruction_data: &[u8]) {
+    let shown = &instruction_data[..instruction_data.len().min(LOGGED_INSTRUCTION_BYTES)];
+    log!(
+        "Could not decode {} byte(s) of instruction data: {:?}{}",
+        instruction_data.len(),
+        shown,
+        if shown.len() < instruction_data.len() { "..." } else { "" }
+    );
+}
+
+/// Make sure at least `n` accounts were passed, so clients get a clear message
+/// instead of a bare error from whichever `next_account_info` call ran out
+fn require_accounts(accounts: &[AccountInfo], n: usize) -> ProgramResult {
+    if accounts.len() < n {
+        log!("Expected {} account(s) but only {} were passed", n, accounts.len());
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    Ok(())
+}
+
+/// Reject the same account in two roles that must differ. Duplicates share one data cell,
+/// so this catches the mistake with a clear error before anything is borrowed or written
+#[cfg(feature = "multi-account")]
+fn require_distinct(first: &AccountInfo, second: &AccountInfo) -> ProgramResult {
+    if first.key == second.key {
+        log!("Account {} was passed twice", first.key);
+        return Err(GreetingError::DuplicateAccount.into());
+    }
+    Ok(())
+}
+
+/// Who to credit with a change, the first signer among the accounts passed or, for
+/// open instructions nobody signed, the greeted account itself
+fn last_writer<'a>(accounts: &'a [AccountInfo], greeted: &'a AccountInfo) -> &'a Pubkey {
+    accounts
+        .iter()
+        .find(|account| account.is_signer)
+        .map_or(greeted.key, |signer| signer.key)
+}
+
+/// The counter `instruction` works from. A running product starts from 1 on its first
+/// sample rather than from whatever the counter held, which marks the product started
+fn starting_counter(
+    #[cfg_attr(not(feature = "arithmetic-ext"), allow(unused_variables))]
+    instruction: &HelloInstruction,
+    state: &mut GreetingAccount,
+) -> Counter {
+    #[cfg(feature = "arithmetic-ext")]
+    if let HelloInstruction::MulSample(_) = instruction {
+        if !state.product_started {
+            state.product_started = true;
+            return 1;
+        }
+    }
+    state.counter
+}
+
+/// Put the greeted account's data length in return data for GetDataLen. Every layout so far
+/// starts with the counter, so that is returned when the data holds one, zero otherwise
+fn get_data_len(account: &AccountInfo) -> Counter {
+    //tooling picks a deserializer from this, so it is a u64 whatever the counter width
+    set_return_data(&(account.data_len() as u64).to_le_bytes());
+    Counter::deserialize(&mut &account.data.borrow()[..]).unwrap_or_default()
+}
+
+/// Write `state` into the account's data, with the `verify-writes` feature reading it back
+/// to make sure what landed is what was meant, which catches buffer size bugs
+fn store_greeting_account(account: &AccountInfo, state: &GreetingAccount) -> ProgramResult {
+    state.serialize(&mut &mut account.data.borrow_mut()[..])?;
+    #[cfg(feature = "verify-writes")]
+    {
+        let written = GreetingAccount::try_from_slice(&account.data.borrow());
+        if written.ok().as_ref() != Some(state) {
+            log!("State read back from {} differs from the state written", account.key);
+            return Err(GreetingError::WriteVerificationFailed.into());
+        }
+    }
+    Ok(())
+}
+
+/// Read the state of an account other than the greeted one, it must be owned by this program too
+#[cfg(feature = "multi-account")]
+fn load_greeting_account(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+) -> Result<GreetingAccount, ProgramError> {
+    if account.owner != program_id {
+        log!("Account {} does not have the correct program id", account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let state = GreetingAccount::try_from_slice(&account.data.borrow())?;
+    #[cfg(feature = "verify-reads")]
+    check_stored_checksum(account, &state)?;
+    Ok(state)
+}
+
+/// Reject a state whose fields no longer add up to its stored checksum, meaning the account
+/// was changed by something other than this program's writes
+#[cfg(feature = "verify-reads")]
+fn check_stored_checksum(account: &AccountInfo, state: &GreetingAccount) -> ProgramResult {
+    if state.checksum != state.fields_checksum()? {
+        log!("State of {} doesn't match its stored checksum", account.key);
+        return Err(GreetingError::StoredChecksumMismatch.into());
+    }
+    Ok(())
+}
+
+/// Copy the serialized state of the greeted account into `destination` for a later restore.
+/// The destination must be owned by this program and at least as large as the state
+#[cfg(feature = "multi-account")]
+fn snapshot(program_id: &Pubkey, source: &GreetingAccount, destination: &AccountInfo) -> ProgramResult {
+    if destination.owner != program_id {
+        log!("Snapshot account does not have the correct program id");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let state = source.try_to_vec()?;
+    let mut destination_data = destination.data.borrow_mut();
+    if destination_data.len() < state.len() {
+        log!(
+            "Snapshot account holds {} bytes but the state needs {}",
+            destination_data.len(),
+            state.len()
+        );
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    destination_data[..state.len()].copy_from_slice(&state);
+
+    log_event("SNAPSHOT", source.counter);
+    Ok(())
+}
+
+/// Log the serialized state as lowercase hex, e.g. `GREETING:EXPORT_HEX 2a00000000`
+#[cfg(feature = "debug-export")]
+fn export_hex(greeting_account: &GreetingAccount) -> ProgramResult {
+    let hex: String = greeting_account
+        .try_to_vec()?
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    log!("{}:EXPORT_HEX {}", LOG_PREFIX, hex);
+    Ok(())
+}
+
+/// Add `delta` to `counter`, failing with `GreetingError::Overflow` if it won't fit.
+/// On overflow the requested delta and the remaining headroom are written to return
+/// data as two little endian counters, so the client can tell the user how far over they went.
+/// A negative delta can only run off the bottom, which is an Underflow with no headroom to report
+fn add_or_report_overflow(counter: Counter, delta: Counter) -> Result<Counter, GreetingError> {
+    #[cfg(feature = "signed-counter")]
+    {
+        if delta < 0 {
+            return counter.checked_add(delta).ok_or_else(|| {
+                log!("Adding {} would take the counter below {}", delta, Counter::MIN);
+                GreetingError::Underflow
+            });
+        }
+    }
+    counter.checked_add(delta).ok_or_else(|| {
+        //only reachable with a positive delta, so the counter is above MAX - delta here
+        let headroom = Counter::MAX - counter;
+        let mut overflow_info = Vec::with_capacity(2 * std::mem::size_of::<Counter>());
+        overflow_info.extend_from_slice(&delta.to_le_bytes());
+        overflow_info.extend_from_slice(&headroom.to_le_bytes());
+        set_return_data(&overflow_info);
+        log!("Adding {} would overflow the counter, headroom is {}", delta, headroom);
+        GreetingError::Overflow
+    })
+}
+
+/// Read the little endian counter at `offset` in an account owned by some other program,
+/// after making sure it really is owned by `owner` and is long enough to hold the value
+#[cfg(feature = "multi-account")]
+fn read_oracle(oracle: &AccountInfo, offset: usize, owner: &Pubkey) -> Result<Counter, ProgramError> {
+    if oracle.owner != owner {
+        log!("Oracle {} is owned by {}, expected {}", oracle.key, oracle.owner, owner);
+        return Err(ProgramError::IllegalOwner);
+    }
+    let data = oracle.data.borrow();
+    let value = offset
+        .checked_add(std::mem::size_of::<Counter>())
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| {
+            log!("Oracle holds {} bytes, too few to read a counter at offset {}", data.len(), offset);
+            ProgramError::AccountDataTooSmall
+        })?;
+    Ok(Counter::try_from_slice(value)?)
+}
+
+/// Move all of `account`'s lamports to `destination` and zero its data, so the runtime
+/// reclaims it at the end of the transaction
+#[cfg(feature = "multi-account")]
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = destination
+        .lamports()
+        .checked_add(account.lamports())
+        .ok_or(GreetingError::Overflow)?;
+    **destination.lamports.borrow_mut() = lamports;
+    **account.lamports.borrow_mut() = 0;
+    account.data.borrow_mut().fill(0);
+    Ok(())
+}
+
+/// Hand a mutation's result back at the state's `return_verbosity`. By default that is the
+/// old and new counter, so optimistic clients can animate the change without reading the
+/// account again. A level this build doesn't know falls back to that default
+fn set_mutation_return_data(previous: Counter, state: &GreetingAccount) {
+    if state.return_verbosity == VERBOSITY_COUNTER {
+        set_return_data(&state.counter.to_le_bytes());
+        return;
+    }
+    let mut data = Vec::with_capacity(2 * std::mem::size_of::<Counter>() + 8 + 32);
+    data.extend_from_slice(&previous.to_le_bytes());
+    data.extend_from_slice(&state.counter.to_le_bytes());
+    if state.return_verbosity == VERBOSITY_FULL {
+        data.extend_from_slice(&state.op_count.to_le_bytes());
+        data.extend_from_slice(state.last_writer.as_ref());
+    }
+    set_return_data(&data);
+}
+
+/// `counter - delta`, or an Underflow error instead of wrapping below the lowest counter
+fn sub_or_report_underflow(counter: Counter, delta: Counter) -> Result<Counter, GreetingError> {
+    counter.checked_sub(delta).ok_or_else(|| {
+        log!("Subtracting {} would take the counter below {}", delta, Counter::MIN);
+        GreetingError::Underflow
+    })
+}
+
+/// `counter - delta`, or an Underflow error if that would go below zero, for a signed
+/// counter too
+#[cfg(feature = "multi-account")]
+fn sub_stopping_at_zero(counter: Counter, delta: Counter) -> Result<Counter, GreetingError> {
+    //in i128 so the same check stops a signed counter at zero
+    let remaining = i128::from(counter) - i128::from(delta);
+    if remaining < 0 {
+        log!("Counter is {}, subtracting {} would take it below zero", counter, delta);
+        return Err(GreetingError::Underflow);
+    }
+    //between zero and the old counter, so it fits
+    Ok(remaining as Counter)
+}
+
+/// Move `counter` by `percent` percent of itself, truncating towards zero, so `-10` takes
+/// 10% off and `-100` brings it to zero. Results outside the counter's range are errors
+#[cfg(feature = "arithmetic-ext")]
+fn adjust_by_percent(counter: Counter, percent: i32) -> Result<Counter, GreetingError> {
+    //any counter up to 64 bits times an i32 fits in an i128
+    let delta = i128::from(counter) * i128::from(percent) / 100;
+    let adjusted = i128::from(counter) + delta;
+    Counter::try_from(adjusted).map_err(|_| {
+        log!("Adjusting {} by {}% leaves the counter out of range", counter, percent);
+        if adjusted > 0 {
+            GreetingError::Overflow
+        } else {
+            GreetingError::Underflow
+        }
+    })
+}
+
+/// Add every delta to `counter` at once. The total is taken in i128, which no list of
+/// counters can overflow, so only the final result is range checked
+#[cfg(feature = "arithmetic-ext")]
+fn add_all(counter: Counter, deltas: &[Counter]) -> Result<Counter, GreetingError> {
+    let total: i128 = deltas.iter().map(|delta| i128::from(*delta)).sum();
+    let sum = i128::from(counter) + total;
+    Counter::try_from(sum).map_err(|_| {
+        log!("Adding {} to {} leaves the counter out of range", total, counter);
+        if sum > 0 {
+            GreetingError::Overflow
+        } else {
+            GreetingError::Underflow
+        }
+    })
+}
+
+/// Multiply `counter` by `sample`. The product is taken in i128, which any counter times a
+/// u32 fits in, so only the result has to be range checked
+#[cfg(feature = "arithmetic-ext")]
+fn multiply_by(counter: Counter, sample: u32) -> Result<Counter, GreetingError> {
+    let product = i128::from(counter) * i128::from(sample);
+    Counter::try_from(product).map_err(|_| {
+        log!("Multiplying {} by {} leaves the counter out of range", counter, sample);
+        if product > 0 {
+            GreetingError::Overflow
+        } else {
+            GreetingError::Underflow
+        }
+    })
+}
+
+/// `fingerprint` as a counter. Every counter but the 32 bit signed one holds all u32s, that
+/// one fails with an Overflow rather than wrapping a large fingerprint negative
+#[cfg(all(feature = "multi-account", feature = "signed-counter"))]
+fn counter_from_checksum(fingerprint: u32) -> Result<Counter, GreetingError> {
+    use std::convert::TryFrom;
+
+    Counter::try_from(fingerprint).map_err(|_| {
+        log!("Checksum {} doesn't fit in the counter", fingerprint);
+        GreetingError::Overflow
+    })
+}
+
+#[cfg(all(feature = "multi-account", not(feature = "signed-counter")))]
+fn counter_from_checksum(fingerprint: u32) -> Result<Counter, GreetingError> {
+    Ok(Counter::from(fingerprint))
+}
+
+/// `scaled`, unless it takes a non zero `counter` to zero where that isn't allowed
+#[cfg(feature = "arithmetic-ext")]
+fn check_zero_result(
+    counter: Counter,
+    scaled: Counter,
+    allow_zero_result: bool,
+) -> Result<Counter, GreetingError> {
+    if scaled == 0 && counter != 0 && !allow_zero_result {
+        log!("Counter is {} and may not be scaled to zero", counter);
+        return Err(GreetingError::ZeroResultForbidden);
+    }
+    Ok(scaled)
+}
+
+/// Emit a structured event line for off-chain consumers, e.g. `GREETING:SET counter=100`
+fn log_event(event: &str, counter: Counter) {
+    log!("{}:{} counter={}", LOG_PREFIX, event, counter);
+}
+
+/// Emit the sequenced change line, e.g. `GREETING:CHANGE seq=7 counter=100`, every write
+/// gets the next seq so a consumer that sees a jump knows it missed an update
+fn log_change(seq: u64, counter: Counter) {
+    log!("{}:CHANGE seq={} counter={}", LOG_PREFIX, seq, counter);
+}
+